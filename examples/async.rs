@@ -0,0 +1,47 @@
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+use picky::{Picker, Source};
+
+// Stands in for an async runtime such as tokio's.
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+fn main() {
+    let (sender, source) = Source::channel();
+    // items trickling in, as from an async stream
+    thread::spawn(move || {
+        for i in 0..100 {
+            thread::sleep(Duration::from_millis(20));
+            if sender.send(format!("item {}", i)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let selection = Picker::new().height(10).select_async(source, false);
+    if let Some(selected) = block_on(selection).unwrap() {
+        println!("{}", selected.join("\n"));
+    }
+}
@@ -1,7 +1,7 @@
 use std::process::Command;
 use std::str;
 
-use picky;
+use picky::Picker;
 
 fn main() {
     let output = Command::new("ps").arg("aux").output().unwrap();
@@ -12,7 +12,14 @@ fn main() {
 
     let lines: Vec<_> = str::from_utf8(&output.stdout).unwrap().lines().collect();
 
-    let result = picky::run(&lines[1..], 10, Some(&lines[0]), true).unwrap();
+    // match the user and command, not the numbers or arguments in between
+    let result = Picker::new()
+        .height(10)
+        .nth("1,11".parse().unwrap())
+        .header(lines[0])
+        .resize(true)
+        .run(&lines[1..])
+        .unwrap();
     if let Some(result) = result {
         println!("{}", result);
     }
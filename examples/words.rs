@@ -1,8 +1,9 @@
-use picky;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
+use picky::{Picker, Source};
+
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
 where
     P: AsRef<Path>,
@@ -16,14 +17,9 @@ fn main() {
         Ok(it) => it,
         _ => return,
     };
-    let mut words = Vec::new();
+    let words = Source::spawn(lines.map_while(Result::ok));
 
-    for line in lines {
-        if let Ok(ip) = line {
-            words.push(ip);
-        }
-    }
-    let result = picky::run(&words, 20, None, false).unwrap();
+    let result = Picker::new().height(20).run_source(words).unwrap();
     if let Some(result) = result {
         println!("{}", result);
     }
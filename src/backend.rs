@@ -0,0 +1,129 @@
+use std::{
+    io::{self, stdout, Stdout, Write},
+    time::Duration,
+};
+
+use crossterm::{cursor, event, event::Event, terminal, Result};
+
+/// Where the picker draws, as a stream of crossterm commands.
+pub trait Backend: Write {
+    fn enable_raw_mode(&mut self) -> Result<()>;
+
+    fn disable_raw_mode(&mut self) -> Result<()>;
+
+    /// Columns and rows of the screen.
+    fn size(&self) -> Result<(u16, u16)>;
+
+    /// Column and row of the cursor, after flushing anything written.
+    fn position(&mut self) -> Result<(u16, u16)>;
+}
+
+/// Where the picker reads keys, mouse events and resizes from, on a thread of their own.
+pub trait Events: Send {
+    /// Whether an event is ready, waiting up to `timeout` for one.
+    fn poll(&mut self, timeout: Duration) -> Result<bool>;
+
+    /// Like `poll`, for when the picker is busy loading items or searching and a key would
+    /// interrupt it.
+    fn poll_busy(&mut self, timeout: Duration) -> Result<bool> {
+        self.poll(timeout)
+    }
+
+    fn read(&mut self) -> Result<Event>;
+}
+
+/// The real terminal, drawn on through stdout.
+pub struct CrosstermBackend(Stdout);
+
+impl CrosstermBackend {
+    pub fn new() -> CrosstermBackend {
+        CrosstermBackend(stdout())
+    }
+}
+
+impl Default for CrosstermBackend {
+    fn default() -> CrosstermBackend {
+        CrosstermBackend::new()
+    }
+}
+
+impl Write for CrosstermBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Backend for CrosstermBackend {
+    fn enable_raw_mode(&mut self) -> Result<()> {
+        terminal::enable_raw_mode()
+    }
+
+    fn disable_raw_mode(&mut self) -> Result<()> {
+        terminal::disable_raw_mode()
+    }
+
+    fn size(&self) -> Result<(u16, u16)> {
+        terminal::size()
+    }
+
+    fn position(&mut self) -> Result<(u16, u16)> {
+        self.flush()?;
+        cursor::position()
+    }
+}
+
+/// Input from the real terminal.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CrosstermEvents;
+
+impl Events for CrosstermEvents {
+    fn poll(&mut self, timeout: Duration) -> Result<bool> {
+        event::poll(timeout)
+    }
+
+    fn read(&mut self) -> Result<Event> {
+        event::read()
+    }
+}
+
+/// A backend to draw on and the events to react to.
+///
+/// `Picker::select_on` runs the picker on one, such as a `TestBackend` driven by
+/// `ScriptedEvents` to check what is drawn.
+pub struct Terminal<B, E> {
+    pub(crate) backend: B,
+    pub(crate) events: E,
+}
+
+impl<B, E> Terminal<B, E>
+where
+    B: Backend,
+    E: Events,
+{
+    pub fn new(backend: B, events: E) -> Terminal<B, E> {
+        Terminal { backend, events }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    pub fn events_mut(&mut self) -> &mut E {
+        &mut self.events
+    }
+}
+
+impl Terminal<CrosstermBackend, CrosstermEvents> {
+    /// The real terminal.
+    pub fn stdout() -> Terminal<CrosstermBackend, CrosstermEvents> {
+        Terminal::new(CrosstermBackend::new(), CrosstermEvents)
+    }
+}
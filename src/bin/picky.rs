@@ -0,0 +1,614 @@
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Read, Write},
+    process::{self, Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use picky::{
+    key_name, parse_keys, Algorithm, CaseMatching, Delimiter, Fields, KeyEvent, KeyMap, Outcome,
+    Picker, PreviewPosition, PreviewSize, Source, Theme, Tiebreak,
+};
+
+const USAGE: &str = "usage: picky [options]
+
+Reads lines from stdin and prints the selected line(s) to stdout.
+
+options:
+    --height N        number of result rows to show (default 10)
+    --header STR      line shown above the results
+    --prompt STR      text drawn in front of the query (default \"> \")
+    -q, --query STR   start with the given query
+    -m, --multi       mark several lines with Tab and Shift-Tab
+    --algo NAME       matching algorithm: skim, clangd, exact, prefix or regex (default skim)
+    -e, --exact       same as --algo exact
+    +x, --no-extended  match the query as a single term instead of fzf's extended syntax
+    -d, --delimiter RE  split lines into fields with a regular expression (default whitespace)
+    -n, --nth N[,N...]  only match these fields: 1 is the first, -1 the last, 2.. or 1..3 ranges
+    --with-nth N[,N...]
+                      only show these fields, still printing the whole line
+    --tiebreak CRIT[,CRIT...]
+                      order results with equal scores by length, begin, end or index, in turn
+                      (default length)
+    -i                case-insensitive matching
+    +i                case-sensitive matching
+    --preview CMD     show the output of CMD for the highlighted line, with {} replaced by it
+    --preview-window POS[:SIZE]
+                      right, bottom or top, with SIZE as N% or a number of columns or rows
+                      (default right:50%)
+    --theme NAME      colors to draw with: default, dark, light or mono
+    --no-color        draw without colors, as when NO_COLOR is set
+    --no-mouse        leave the mouse to the terminal instead of clicking and scrolling results
+    --bind KEY:ACTION[,KEY:ACTION...]
+                      bind keys to actions, e.g. ctrl-j:down,ctrl-k:up,alt-enter:toggle
+    --expect KEY[,KEY...]
+                      also accept with these keys, printing the key that was pressed on the
+                      first line (an empty line for any other way of accepting)
+    -h, --help        print this help
+
+exit codes:
+    0    a line was selected
+    1    nothing was selected
+    2    error
+    130  aborted with Esc or Ctrl-C";
+
+// Longest a preview command may run before it's killed, and how often it's checked on.
+const PREVIEW_TIMEOUT: Duration = Duration::from_secs(5);
+const PREVIEW_POLL: Duration = Duration::from_millis(10);
+
+type Input = Box<dyn Read + Send>;
+type Output = Box<dyn Write>;
+
+struct Options {
+    height: u16,
+    header: Option<String>,
+    prompt: Option<String>,
+    query: Option<String>,
+    multi: bool,
+    case: Option<CaseMatching>,
+    algorithm: Algorithm,
+    extended: bool,
+    delimiter: Option<Delimiter>,
+    nth: Option<Fields>,
+    with_nth: Option<Fields>,
+    tiebreaks: Option<Vec<Tiebreak>>,
+    preview: Option<String>,
+    preview_window: Option<(PreviewPosition, PreviewSize)>,
+    colors: bool,
+    theme: Option<Theme>,
+    mouse: bool,
+    keymap: KeyMap,
+    expect: Vec<KeyEvent>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            height: 10,
+            header: None,
+            prompt: None,
+            query: None,
+            multi: false,
+            case: None,
+            algorithm: Algorithm::SkimV2,
+            extended: true,
+            delimiter: None,
+            nth: None,
+            with_nth: None,
+            tiebreaks: None,
+            preview: None,
+            preview_window: None,
+            colors: true,
+            theme: None,
+            mouse: true,
+            keymap: KeyMap::default(),
+            expect: Vec::new(),
+        }
+    }
+}
+
+fn parse_args<I>(args: I) -> Result<Option<Options>, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        // accept both `--flag value` and `--flag=value`
+        let (flag, inline) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => {
+                (arg[..i].to_string(), Some(arg[i + 1..].to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} requires a value", flag))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--height" => {
+                let height = value()?;
+                options.height = height
+                    .parse()
+                    .map_err(|_| format!("invalid height: {}", height))?;
+            }
+            "--header" => options.header = Some(value()?),
+            "--prompt" => options.prompt = Some(value()?),
+            "-q" | "--query" => options.query = Some(value()?),
+            "-m" | "--multi" => options.multi = true,
+            "--algo" => {
+                let algorithm = value()?;
+                options.algorithm = match algorithm.as_str() {
+                    "skim" => Algorithm::SkimV2,
+                    "clangd" => Algorithm::Clangd,
+                    "exact" => Algorithm::Exact,
+                    "prefix" => Algorithm::Prefix,
+                    "regex" => Algorithm::Regex,
+                    _ => return Err(format!("unknown algorithm: {}", algorithm)),
+                };
+            }
+            "-e" | "--exact" => options.algorithm = Algorithm::Exact,
+            "+x" | "--no-extended" => options.extended = false,
+            "-d" | "--delimiter" => {
+                let delimiter = value()?.parse::<Delimiter>();
+                options.delimiter = Some(delimiter.map_err(|e| e.to_string())?);
+            }
+            "-n" | "--nth" => {
+                let nth = value()?.parse::<Fields>();
+                options.nth = Some(nth.map_err(|e| e.to_string())?);
+            }
+            "--with-nth" => {
+                let with_nth = value()?.parse::<Fields>();
+                options.with_nth = Some(with_nth.map_err(|e| e.to_string())?);
+            }
+            "--tiebreak" => {
+                let tiebreaks = Tiebreak::parse_chain(&value()?);
+                options.tiebreaks = Some(tiebreaks.map_err(|e| e.to_string())?);
+            }
+            "-i" => options.case = Some(CaseMatching::Ignore),
+            "+i" => options.case = Some(CaseMatching::Respect),
+            "--preview" => options.preview = Some(value()?),
+            "--preview-window" => {
+                let window = value()?;
+                options.preview_window = Some(
+                    parse_preview_window(&window)
+                        .ok_or_else(|| format!("invalid preview window: {}", window))?,
+                );
+            }
+            "--theme" => {
+                let theme = value()?;
+                options.theme = Some(match theme.as_str() {
+                    "default" => Theme::default(),
+                    "dark" => Theme::dark(),
+                    "light" => Theme::light(),
+                    "mono" => Theme::monochrome(),
+                    _ => return Err(format!("unknown theme: {}", theme)),
+                });
+            }
+            "--no-color" => options.colors = false,
+            "--no-mouse" => options.mouse = false,
+            "--bind" => options
+                .keymap
+                .parse_bindings(&value()?)
+                .map_err(|e| e.to_string())?,
+            "--expect" => {
+                let keys = parse_keys(&value()?).map_err(|e| e.to_string())?;
+                options.expect.extend(keys);
+            }
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+
+    Ok(Some(options))
+}
+
+fn parse_preview_window(window: &str) -> Option<(PreviewPosition, PreviewSize)> {
+    let mut parts = window.splitn(2, ':');
+    let position = match parts.next()? {
+        "right" => PreviewPosition::Right,
+        "bottom" => PreviewPosition::Bottom,
+        "top" => PreviewPosition::Top,
+        _ => return None,
+    };
+    let size = match parts.next() {
+        None => PreviewSize::Percent(50),
+        Some(size) => match size.strip_suffix('%') {
+            Some(percent) => PreviewSize::Percent(percent.parse().ok()?),
+            None => PreviewSize::Fixed(size.parse().ok()?),
+        },
+    };
+    Some((position, size))
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+// Run the preview command for `line`, returning what it printed, or that it took longer than
+// `timeout`.
+fn preview(command: &str, line: &str, timeout: Duration) -> String {
+    let command = command.replace("{}", &shell_quote(line));
+    let mut shell = Command::new("sh");
+    shell
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    #[cfg(unix)]
+    {
+        // a group of its own, to kill whatever it starts along with it
+        use std::os::unix::process::CommandExt;
+        shell.process_group(0);
+    }
+    let mut child = match shell.spawn() {
+        Ok(child) => child,
+        Err(e) => return e.to_string(),
+    };
+
+    // read as it runs, so it never waits on a full pipe
+    let stdout = child.stdout.take().map(read_all);
+    let stderr = child.stderr.take().map(read_all);
+    let start = Instant::now();
+    while let Ok(None) = child.try_wait() {
+        if start.elapsed() > timeout {
+            kill(&mut child);
+            return format!("preview timed out after {:?}", timeout);
+        }
+        thread::sleep(PREVIEW_POLL);
+    }
+
+    let mut text = String::new();
+    for output in stdout.into_iter().chain(stderr) {
+        let output = output.join().unwrap_or_default();
+        text.push_str(&String::from_utf8_lossy(&output));
+    }
+    text
+}
+
+fn read_all(mut stream: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut read = Vec::new();
+        let _ = stream.read_to_end(&mut read);
+        read
+    })
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.wait();
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+// Point stdin and stdout at the terminal so the picker can draw inside a pipeline,
+// returning the original streams for reading items and writing the selection.
+#[cfg(unix)]
+fn attach_tty() -> io::Result<(Input, Output)> {
+    use std::{
+        fs::File,
+        os::unix::io::{AsRawFd, FromRawFd},
+    };
+
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected lines on stdin",
+        ));
+    }
+
+    let tty = File::options().read(true).write(true).open("/dev/tty")?;
+
+    unsafe {
+        let input = libc::dup(libc::STDIN_FILENO);
+        if input < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let input = File::from_raw_fd(input);
+
+        let output = libc::dup(libc::STDOUT_FILENO);
+        if output < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let output = File::from_raw_fd(output);
+
+        for fd in &[libc::STDIN_FILENO, libc::STDOUT_FILENO] {
+            if libc::dup2(tty.as_raw_fd(), *fd) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok((Box::new(input), Box::new(output)))
+    }
+}
+
+#[cfg(windows)]
+fn attach_tty() -> io::Result<(Input, Output)> {
+    use std::{
+        ffi::c_void,
+        fs::File,
+        io::IsTerminal,
+        os::windows::io::{AsHandle, IntoRawHandle},
+    };
+
+    const STD_INPUT_HANDLE: u32 = -10i32 as u32;
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    extern "system" {
+        fn SetStdHandle(std_handle: u32, handle: *mut c_void) -> i32;
+    }
+
+    if io::stdin().is_terminal() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected lines on stdin",
+        ));
+    }
+
+    let input = File::from(io::stdin().as_handle().try_clone_to_owned()?);
+    let output = File::from(io::stdout().as_handle().try_clone_to_owned()?);
+    // keys and drawing go through the console, whatever the streams are redirected to
+    for (std_handle, console) in &[(STD_INPUT_HANDLE, "CONIN$"), (STD_OUTPUT_HANDLE, "CONOUT$")] {
+        let console = File::options().read(true).write(true).open(console)?;
+        if unsafe { SetStdHandle(*std_handle, console.into_raw_handle()) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok((Box::new(input), Box::new(output)))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn attach_tty() -> io::Result<(Input, Output)> {
+    Ok((Box::new(io::stdin()), Box::new(io::stdout())))
+}
+
+fn lines(input: Input) -> impl Iterator<Item = String> {
+    BufReader::new(input)
+        .split(b'\n')
+        .map_while(Result::ok)
+        .map(|mut line| {
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            String::from_utf8_lossy(&line).into_owned()
+        })
+}
+
+fn run(options: Options) -> Result<i32, String> {
+    let (input, mut output) = attach_tty().map_err(|e| e.to_string())?;
+
+    let mut picker = Picker::new()
+        .height(options.height)
+        .algorithm(options.algorithm)
+        .extended(options.extended)
+        .mouse(options.mouse)
+        .keymap(options.keymap);
+    if !options.colors {
+        picker = picker.colors(false);
+    }
+    if let Some(theme) = options.theme {
+        picker = picker.theme(theme);
+    }
+    if let Some(header) = options.header {
+        picker = picker.header(header);
+    }
+    if let Some(prompt) = options.prompt {
+        picker = picker.prompt(prompt);
+    }
+    if let Some(query) = options.query {
+        picker = picker.query(query);
+    }
+    if let Some(case) = options.case {
+        picker = picker.case(case);
+    }
+    if let Some(delimiter) = options.delimiter {
+        picker = picker.delimiter(delimiter);
+    }
+    if let Some(nth) = options.nth {
+        picker = picker.nth(nth);
+    }
+    if let Some(with_nth) = options.with_nth {
+        picker = picker.with_nth(with_nth);
+    }
+    if let Some(tiebreaks) = options.tiebreaks {
+        picker = picker.tiebreak(tiebreaks);
+    }
+    if let Some(command) = options.preview {
+        picker = picker.preview(move |line: &String| preview(&command, line, PREVIEW_TIMEOUT));
+    }
+    if let Some((position, size)) = options.preview_window {
+        picker = picker.preview_window(position, size);
+    }
+    for &key in &options.expect {
+        picker = picker.expect(key);
+    }
+
+    let outcome = picker
+        .outcome(Source::spawn(lines(input)), options.multi)
+        .map_err(|e| e.to_string())?;
+    finish(&outcome, &options.expect, &mut output).map_err(|e| e.to_string())
+}
+
+// Print what was accepted, after the key it was accepted with if any keys are expected, and
+// return the exit code.
+fn finish(
+    outcome: &Outcome<String>,
+    expect: &[KeyEvent],
+    output: &mut impl Write,
+) -> io::Result<i32> {
+    if !outcome.is_accepted() {
+        return Ok(130);
+    }
+
+    if !expect.is_empty() {
+        let key = outcome
+            .key
+            .filter(|key| expect.contains(key))
+            .map(key_name)
+            .unwrap_or_default();
+        writeln!(output, "{}", key)?;
+    }
+    for line in &outcome.items {
+        writeln!(output, "{}", line)?;
+    }
+    if outcome.items.is_empty() {
+        Ok(1)
+    } else {
+        Ok(0)
+    }
+}
+
+fn main() {
+    let code = match parse_args(env::args().skip(1)) {
+        Ok(Some(options)) => run(options).unwrap_or_else(|e| {
+            eprintln!("picky: {}", e);
+            2
+        }),
+        Ok(None) => {
+            println!("{}", USAGE);
+            0
+        }
+        Err(e) => {
+            eprintln!("picky: {}\n\n{}", e, USAGE);
+            2
+        }
+    };
+    process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use picky::{Action, KeyCode, KeyModifiers};
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Options>, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse(&[
+            "--height=5",
+            "-q",
+            "foo",
+            "--multi",
+            "-e",
+            "+x",
+            "+i",
+            "--tiebreak",
+            "begin,index",
+            "--preview-window",
+            "bottom:3",
+            "--bind",
+            "ctrl-j:down",
+            "--expect=ctrl-x,alt-,",
+            "--no-color",
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(options.height, 5);
+        assert_eq!(options.query.as_deref(), Some("foo"));
+        assert!(options.multi);
+        assert_eq!(options.algorithm, Algorithm::Exact);
+        assert!(!options.extended);
+        assert_eq!(options.case, Some(CaseMatching::Respect));
+        assert_eq!(
+            options.tiebreaks,
+            Some(vec![Tiebreak::Begin, Tiebreak::Index])
+        );
+        assert_eq!(
+            options.preview_window,
+            Some((PreviewPosition::Bottom, PreviewSize::Fixed(3)))
+        );
+        let ctrl_j = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::CONTROL);
+        assert_eq!(options.keymap.action(ctrl_j), Some(Action::Down));
+        assert_eq!(options.expect.len(), 2);
+        assert!(!options.colors);
+
+        let defaults = parse(&[]).unwrap().unwrap();
+        assert_eq!(defaults.height, 10);
+        assert_eq!(defaults.algorithm, Algorithm::SkimV2);
+        assert!(defaults.extended && defaults.colors && defaults.mouse);
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        assert!(parse(&["-h"]).unwrap().is_none());
+        for args in &[
+            &["--height"][..],
+            &["--height", "tall"],
+            &["--algo", "magic"],
+            &["--theme", "neon"],
+            &["--preview-window", "left"],
+            &["--bind", "ctrl-j"],
+            &["--frobnicate"],
+        ] {
+            assert!(parse(args).is_err(), "{:?}", args);
+        }
+    }
+
+    fn outcome(action: Action, items: &[&str], key: KeyEvent) -> Outcome<String> {
+        Outcome {
+            items: items.iter().map(|s| s.to_string()).collect(),
+            indices: (0..items.len()).collect(),
+            query: String::new(),
+            action,
+            key: Some(key),
+        }
+    }
+
+    #[test]
+    fn exits_with_what_was_chosen() {
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::empty());
+        let ctrl_x = KeyEvent::new(KeyCode::Char('x'), KeyModifiers::CONTROL);
+        let finished = |outcome: Outcome<String>, expect: &[KeyEvent]| {
+            let mut output = Vec::new();
+            let code = finish(&outcome, expect, &mut output).unwrap();
+            (code, String::from_utf8(output).unwrap())
+        };
+
+        let accepted = outcome(Action::Accept, &["a", "b"], enter);
+        assert_eq!(finished(accepted, &[]), (0, "a\nb\n".to_string()));
+        let empty = outcome(Action::Accept, &[], enter);
+        assert_eq!(finished(empty, &[]), (1, String::new()));
+        let aborted = outcome(Action::Abort, &[], enter);
+        assert_eq!(finished(aborted, &[ctrl_x]), (130, String::new()));
+
+        // the key comes first when keys are expected, and is blank for any other
+        let expected = outcome(Action::Accept, &["a"], ctrl_x);
+        assert_eq!(
+            finished(expected, &[ctrl_x]),
+            (0, "ctrl-x\na\n".to_string())
+        );
+        let other = outcome(Action::Accept, &["a"], enter);
+        assert_eq!(finished(other, &[ctrl_x]), (0, "\na\n".to_string()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn previews_what_the_command_prints() {
+        let timeout = Duration::from_secs(5);
+        assert_eq!(preview("echo {}", "it's a line", timeout), "it's a line\n");
+        assert_eq!(preview("echo {} >&2", "err", timeout), "err\n");
+
+        let start = Instant::now();
+        let timeout = Duration::from_millis(100);
+        let text = preview("echo started; sleep 5", "", timeout);
+        assert_eq!(text, "preview timed out after 100ms");
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
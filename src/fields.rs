@@ -0,0 +1,248 @@
+use std::{ops::Range, str::FromStr, sync::Arc};
+
+use crate::regex::Regex;
+use crate::{Matcher, ParseError};
+
+/// How items are split into fields.
+///
+/// By default fields are separated by runs of whitespace, as in AWK. Each field keeps the
+/// delimiter after it, so fields shown side by side read as they did in the item.
+#[derive(Clone, Debug, Default)]
+pub struct Delimiter(Option<Regex>);
+
+impl Delimiter {
+    pub fn whitespace() -> Delimiter {
+        Delimiter(None)
+    }
+
+    /// Fields separated by matches of a regular expression, such as `,` or `:+`.
+    pub fn pattern(pattern: &str) -> Result<Delimiter, ParseError> {
+        Regex::new(pattern, false)
+            .map(|regex| Delimiter(Some(regex)))
+            .ok_or_else(|| ParseError(format!("invalid delimiter: {}", pattern)))
+    }
+
+    // Char ranges of the fields of `text`, each with the delimiter after it.
+    fn split(&self, text: &[char]) -> Vec<Field> {
+        let mut fields = Vec::new();
+        let mut start = 0;
+        match &self.0 {
+            None => {
+                // leading whitespace isn't part of any field
+                start = text.iter().take_while(|c| c.is_whitespace()).count();
+                while start < text.len() {
+                    let end = start
+                        + text[start..]
+                            .iter()
+                            .take_while(|c| !c.is_whitespace())
+                            .count();
+                    let next = end + text[end..].iter().take_while(|c| c.is_whitespace()).count();
+                    fields.push(Field { start, end, next });
+                    start = next;
+                }
+            }
+            Some(regex) => {
+                let mut from = 0;
+                while from <= text.len() {
+                    match regex.find_at(text, from) {
+                        // an empty match can't separate anything, so look past it
+                        Some((a, b)) if a == b => from = a + 1,
+                        Some((a, b)) => {
+                            fields.push(Field {
+                                start,
+                                end: a,
+                                next: b,
+                            });
+                            start = b;
+                            from = start;
+                        }
+                        None => break,
+                    }
+                }
+                if start < text.len() {
+                    fields.push(Field {
+                        start,
+                        end: text.len(),
+                        next: text.len(),
+                    });
+                }
+            }
+        }
+        fields
+    }
+}
+
+impl FromStr for Delimiter {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Delimiter, ParseError> {
+        Delimiter::pattern(s)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Field {
+    start: usize,
+    // end of the text, before the delimiter
+    end: usize,
+    // end of the delimiter
+    next: usize,
+}
+
+/// Fields picked by index, written like fzf's field index expressions.
+///
+/// `1` is the first field and `-1` the last; `2..`, `..3` and `2..4` are inclusive ranges.
+/// Several can be given separated by commas, as in `1,3..`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fields(Vec<(Option<isize>, Option<isize>)>);
+
+impl Fields {
+    // Indices of the chosen fields out of `count`, in the order they were asked for.
+    fn resolve(&self, count: usize) -> Vec<usize> {
+        let index = |i: isize| {
+            if i > 0 {
+                i - 1
+            } else {
+                count as isize + i
+            }
+        };
+        let mut chosen = Vec::new();
+        for &(start, end) in &self.0 {
+            let start = start.map_or(0, index).max(0);
+            let end = end
+                .map_or(count as isize - 1, index)
+                .min(count as isize - 1);
+            chosen.extend((start..=end).map(|i| i as usize));
+        }
+        chosen
+    }
+}
+
+impl FromStr for Fields {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Fields, ParseError> {
+        let error = || ParseError(format!("invalid field index expression: {}", s));
+        let index = |i: &str| match i.parse::<isize>() {
+            Ok(0) | Err(_) => Err(error()),
+            Ok(i) => Ok(i),
+        };
+
+        let ranges = s
+            .split(',')
+            .map(|range| match range.find("..") {
+                Some(dots) => {
+                    let (start, end) = (&range[..dots], &range[dots + 2..]);
+                    let start = Some(start).filter(|s| !s.is_empty()).map(index);
+                    let end = Some(end).filter(|e| !e.is_empty()).map(index);
+                    Ok((start.transpose()?, end.transpose()?))
+                }
+                None => {
+                    let i = index(range)?;
+                    Ok((Some(i), Some(i)))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Fields(ranges))
+    }
+}
+
+/// The fields items are searched and shown by.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FieldOptions {
+    pub(crate) delimiter: Delimiter,
+    pub(crate) nth: Option<Fields>,
+    pub(crate) with_nth: Option<Fields>,
+}
+
+impl FieldOptions {
+    pub(crate) fn searches_fields(&self) -> bool {
+        self.nth.is_some() || self.with_nth.is_some()
+    }
+
+    // Char ranges of `chosen` fields, leaving out the delimiter after the last one.
+    fn ranges(fields: &[Field], chosen: &[usize]) -> Vec<Range<usize>> {
+        chosen
+            .iter()
+            .enumerate()
+            .map(|(i, &f)| {
+                let field = fields[f];
+                let end = if i + 1 == chosen.len() {
+                    field.end
+                } else {
+                    field.next
+                };
+                field.start..end
+            })
+            .collect()
+    }
+
+    fn shown_fields(&self, fields: &[Field]) -> Vec<usize> {
+        match &self.with_nth {
+            Some(with_nth) => with_nth.resolve(fields.len()),
+            None => (0..fields.len()).collect(),
+        }
+    }
+
+    /// Char ranges of `text` shown in the results, or `None` to show all of it.
+    pub(crate) fn shown(&self, text: &str) -> Option<Vec<Range<usize>>> {
+        self.with_nth.as_ref()?;
+        let chars: Vec<_> = text.chars().collect();
+        let fields = self.delimiter.split(&chars);
+        Some(FieldOptions::ranges(&fields, &self.shown_fields(&fields)))
+    }
+
+    // Char ranges of `text` the query is matched against. `--nth` picks from the fields that
+    // are shown, as in fzf.
+    fn searched(&self, text: &[char]) -> Vec<Range<usize>> {
+        let fields = self.delimiter.split(text);
+        let shown = self.shown_fields(&fields);
+        let chosen = match &self.nth {
+            Some(nth) => nth.resolve(shown.len()).iter().map(|&i| shown[i]).collect(),
+            None => shown,
+        };
+        FieldOptions::ranges(&fields, &chosen)
+    }
+}
+
+/// Wraps a matcher to only match the chosen fields of each item.
+pub(crate) struct FieldMatcher {
+    matcher: Arc<dyn Matcher>,
+    options: FieldOptions,
+}
+
+impl FieldMatcher {
+    pub(crate) fn new(matcher: Arc<dyn Matcher>, options: FieldOptions) -> FieldMatcher {
+        FieldMatcher { matcher, options }
+    }
+}
+
+impl Matcher for FieldMatcher {
+    fn match_indices(&self, choice: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+        let chars: Vec<_> = choice.chars().collect();
+        // the searched text, and where each of its chars is in `choice`
+        let mut text = String::new();
+        let mut positions = Vec::new();
+        for range in self.options.searched(&chars) {
+            text.extend(&chars[range.clone()]);
+            positions.extend(range);
+        }
+        if positions.is_empty() {
+            return None;
+        }
+
+        let (score, indices) = self.matcher.match_indices(&text, query)?;
+        // fields can be searched out of order, but matches are kept in order
+        let mut indices: Vec<_> = indices.into_iter().map(|i| positions[i]).collect();
+        indices.sort_unstable();
+        Some((score, indices))
+    }
+
+    fn narrows(&self, previous: &str, query: &str) -> bool {
+        self.matcher.narrows(previous, query)
+    }
+
+    fn required(&self, query: &str) -> String {
+        self.matcher.required(query)
+    }
+}
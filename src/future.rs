@@ -0,0 +1,177 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crossterm::{ErrorKind, Result};
+
+use crate::waker;
+
+#[derive(Debug)]
+struct State<T> {
+    result: Option<Result<Option<Vec<T>>>>,
+    waker: Option<Waker>,
+}
+
+/// The outcome of a picker running on its own thread, returned by `Picker::select_async`.
+///
+/// Resolves to what `Picker::select` would have returned, once the picker is accepted or
+/// aborted, or to an error if the picker panics. Awaiting it never blocks the runtime, since
+/// only the picker's thread waits for keys; dropping it closes the picker, so it can be
+/// cancelled like any other future.
+#[derive(Debug)]
+pub struct Selection<T> {
+    state: Arc<Mutex<State<T>>>,
+    picker: waker::Waker,
+}
+
+impl<T> Future for Selection<T> {
+    type Output = Result<Option<Vec<T>>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Drop for Selection<T> {
+    fn drop(&mut self) {
+        // nothing if the picker already closed
+        self.picker.close();
+    }
+}
+
+// The picker thread's end of a `Selection`, which resolves it even if the picker panics.
+pub(crate) struct Completion<T>(Option<Arc<Mutex<State<T>>>>);
+
+impl<T> Completion<T> {
+    pub(crate) fn complete(mut self, result: Result<Option<Vec<T>>>) {
+        if let Some(state) = self.0.take() {
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        if self.0.is_some() {
+            let panicked = io::Error::other("picker thread panicked");
+            Completion(self.0.take()).complete(Err(ErrorKind::IoError(panicked)));
+        }
+    }
+}
+
+// A selection of the picker that `picker` wakes, which dropping it closes.
+pub(crate) fn selection<T>(picker: waker::Waker) -> (Selection<T>, Completion<T>) {
+    let state = Arc::new(Mutex::new(State {
+        result: None,
+        waker: None,
+    }));
+    let completion = Completion(Some(state.clone()));
+    (Selection { state, picker }, completion)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::{channel, Sender},
+        task::Wake,
+        thread,
+        time::Duration,
+    };
+
+    use crossterm::event::Event;
+
+    use super::*;
+    use crate::{Action, Events, Picker, Source, Terminal, TestBackend};
+
+    // Tells the test each time the future wakes it.
+    struct Woken(Mutex<Sender<()>>);
+
+    impl Wake for Woken {
+        fn wake(self: Arc<Self>) {
+            let _ = self.0.lock().unwrap().send(());
+        }
+    }
+
+    fn poll<T>(selection: &mut Selection<T>, waker: &Waker) -> Poll<Result<Option<Vec<T>>>> {
+        Pin::new(selection).poll(&mut Context::from_waker(waker))
+    }
+
+    #[test]
+    fn resolves_to_the_selection() {
+        let (mut selection, completion) = selection(waker::Waker::default());
+        let (woken, wakes) = channel();
+        let waker = Waker::from(Arc::new(Woken(Mutex::new(woken))));
+        assert!(poll(&mut selection, &waker).is_pending());
+
+        thread::spawn(move || completion.complete(Ok(Some(vec!["dogs"]))));
+        wakes.recv_timeout(Duration::from_secs(5)).unwrap();
+        match poll(&mut selection, &waker) {
+            Poll::Ready(Ok(selected)) => assert_eq!(selected, Some(vec!["dogs"])),
+            _ => panic!("not resolved"),
+        }
+    }
+
+    #[test]
+    fn resolves_to_an_error_if_the_picker_panics() {
+        let (mut selection, completion) = selection::<&str>(waker::Waker::default());
+        let (woken, wakes) = channel();
+        let waker = Waker::from(Arc::new(Woken(Mutex::new(woken))));
+        assert!(poll(&mut selection, &waker).is_pending());
+
+        let picker = thread::spawn(move || {
+            let _completion = completion;
+            panic!("picker panicked");
+        });
+        assert!(picker.join().is_err());
+        wakes.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(poll(&mut selection, &waker), Poll::Ready(Err(_))));
+    }
+
+    // Keys that never come.
+    struct Untyped;
+
+    impl Events for Untyped {
+        fn poll(&mut self, timeout: Duration) -> Result<bool> {
+            thread::sleep(timeout);
+            Ok(false)
+        }
+
+        fn read(&mut self) -> Result<Event> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn dropping_it_closes_the_picker() {
+        let picker = waker::Waker::default();
+        let (selection, _completion) = selection::<String>(picker.clone());
+        let (closed, close) = channel();
+        thread::spawn(move || {
+            let mut terminal = Terminal::new(TestBackend::new(30, 10), Untyped);
+            let source = Some(Source::from(vec!["dogs".to_string()]));
+            let outcome = Picker::new().session(&mut terminal, Vec::new(), source, false, picker);
+            let _ = closed.send((outcome.unwrap(), terminal));
+        });
+        thread::sleep(Duration::from_millis(100));
+
+        drop(selection);
+        let (outcome, terminal) = close.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(outcome.action, Action::Abort);
+        assert!(!terminal.backend().is_raw());
+    }
+}
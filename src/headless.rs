@@ -0,0 +1,362 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    thread,
+    time::Duration,
+};
+
+use crossterm::{
+    event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent},
+    ErrorKind, Result,
+};
+
+use crate::backend::{Backend, Events};
+use crate::width::char_width;
+
+/// An in-memory screen that understands the escape codes the picker draws with.
+///
+/// Only text is kept on screen, not colors or attributes, though everything written can be
+/// looked at with `output`. Every flush that changes the screen is kept
+/// as a frame, so what was drawn can be checked after the picker has closed and cleared it.
+/// Text running past the right edge is cut off rather than wrapped.
+#[derive(Clone, Debug)]
+pub struct TestBackend {
+    width: u16,
+    height: u16,
+    // one grapheme per cell, with an empty cell after a wide one
+    cells: Vec<Vec<String>>,
+    cursor: (u16, u16),
+    saved: (u16, u16),
+    // bytes of an incomplete escape code or char
+    pending: Vec<u8>,
+    written: Vec<u8>,
+    frames: Vec<Vec<String>>,
+    raw: bool,
+}
+
+impl TestBackend {
+    pub fn new(width: u16, height: u16) -> TestBackend {
+        TestBackend {
+            width,
+            height,
+            cells: vec![TestBackend::blank_row(width); height as usize],
+            cursor: (0, 0),
+            saved: (0, 0),
+            pending: Vec::new(),
+            written: Vec::new(),
+            frames: Vec::new(),
+            raw: false,
+        }
+    }
+
+    fn blank_row(width: u16) -> Vec<String> {
+        vec![" ".to_string(); width as usize]
+    }
+
+    /// Start drawing from `row` rather than the top of the screen.
+    pub fn at_row(mut self, row: u16) -> TestBackend {
+        self.cursor = (0, row.min(self.height.saturating_sub(1)));
+        self
+    }
+
+    /// The screen as lines of text, without trailing spaces.
+    pub fn lines(&self) -> Vec<String> {
+        self.cells
+            .iter()
+            .map(|row| row.concat().trim_end().to_string())
+            .collect()
+    }
+
+    /// Every distinct screen flushed so far, oldest first.
+    pub fn frames(&self) -> &[Vec<String>] {
+        &self.frames
+    }
+
+    /// Everything written so far, escape codes and all.
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.written).into_owned()
+    }
+
+    /// Column and row of the cursor.
+    pub fn cursor(&self) -> (u16, u16) {
+        self.cursor
+    }
+
+    pub fn is_raw(&self) -> bool {
+        self.raw
+    }
+
+    fn print(&mut self, c: char) {
+        let (column, row) = (self.cursor.0 as usize, self.cursor.1 as usize);
+        let width = char_width(c);
+        if width == 0 {
+            // combining characters join the cell before them
+            if let Some(cell) = column
+                .checked_sub(1)
+                .and_then(|column| self.cells[row].get_mut(column))
+            {
+                cell.push(c);
+            }
+            return;
+        }
+        if column + width > self.width as usize {
+            return;
+        }
+        self.cells[row][column] = c.to_string();
+        if width == 2 {
+            self.cells[row][column + 1] = String::new();
+        }
+        self.cursor.0 += width as u16;
+    }
+
+    fn csi(&mut self, params: &str, command: char) {
+        let numbers: Vec<u16> = params
+            .trim_start_matches('?')
+            .split(';')
+            .map(|n| n.parse().unwrap_or(0))
+            .collect();
+        // like a terminal, moving by zero moves by one
+        let n = numbers.first().copied().unwrap_or(0).max(1);
+        let (right, bottom) = (self.width.saturating_sub(1), self.height.saturating_sub(1));
+        let (column, row) = &mut self.cursor;
+        match command {
+            'A' => *row = row.saturating_sub(n),
+            'B' => *row = (*row + n).min(bottom),
+            'C' => *column = (*column + n).min(right),
+            'D' => *column = column.saturating_sub(n),
+            'E' => {
+                *row = (*row + n).min(bottom);
+                *column = 0;
+            }
+            'G' => *column = (n - 1).min(right),
+            'H' => {
+                *row = (n - 1).min(bottom);
+                *column = (numbers.get(1).copied().unwrap_or(1).max(1) - 1).min(right);
+            }
+            // only clearing from the cursor down
+            'J' if matches!(params, "" | "0") => {
+                let (column, row) = (*column as usize, *row as usize);
+                for cell in &mut self.cells[row][column..] {
+                    *cell = " ".to_string();
+                }
+                for line in &mut self.cells[row + 1..] {
+                    *line = TestBackend::blank_row(self.width);
+                }
+            }
+            'K' => {
+                let (column, row) = (*column as usize, *row as usize);
+                for cell in &mut self.cells[row][column..] {
+                    *cell = " ".to_string();
+                }
+            }
+            'S' => {
+                for _ in 0..n.min(self.height) {
+                    self.cells.remove(0);
+                    self.cells.push(TestBackend::blank_row(self.width));
+                }
+            }
+            // colors, attributes, mouse capture and resizing don't change the text
+            _ => {}
+        }
+    }
+
+    // Apply as much of the pending output as is complete.
+    fn process(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        let mut start = 0;
+        while start < pending.len() {
+            let rest = &pending[start..];
+            let used = if rest[0] == 0x1b {
+                match rest.get(1) {
+                    None => break,
+                    Some(b'7') => {
+                        self.saved = self.cursor;
+                        2
+                    }
+                    Some(b'8') => {
+                        self.cursor = self.saved;
+                        2
+                    }
+                    Some(b'[') => match rest[2..].iter().position(|b| (0x40..=0x7e).contains(b)) {
+                        Some(end) => {
+                            let params = String::from_utf8_lossy(&rest[2..2 + end]).into_owned();
+                            self.csi(&params, rest[2 + end] as char);
+                            end + 3
+                        }
+                        None => break,
+                    },
+                    Some(_) => 2,
+                }
+            } else {
+                let len = match rest[0] {
+                    0x00..=0x7f => 1,
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    _ => 4,
+                };
+                if rest.len() < len {
+                    break;
+                }
+                match std::str::from_utf8(&rest[..len]) {
+                    Ok(s) => {
+                        for c in s.chars() {
+                            match c {
+                                '\r' => self.cursor.0 = 0,
+                                '\n' => self.cursor.1 = (self.cursor.1 + 1).min(self.height - 1),
+                                c if c.is_control() => {}
+                                c => self.print(c),
+                            }
+                        }
+                    }
+                    Err(_) => self.print(char::REPLACEMENT_CHARACTER),
+                }
+                len
+            };
+            start += used;
+        }
+        self.pending = pending[start..].to_vec();
+    }
+}
+
+impl Write for TestBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        self.written.extend_from_slice(buf);
+        self.process();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let lines = self.lines();
+        if self.frames.last() != Some(&lines) {
+            self.frames.push(lines);
+        }
+        Ok(())
+    }
+}
+
+impl Backend for TestBackend {
+    fn enable_raw_mode(&mut self) -> Result<()> {
+        self.raw = true;
+        Ok(())
+    }
+
+    fn disable_raw_mode(&mut self) -> Result<()> {
+        self.raw = false;
+        Ok(())
+    }
+
+    fn size(&self) -> Result<(u16, u16)> {
+        Ok((self.width, self.height))
+    }
+
+    fn position(&mut self) -> Result<(u16, u16)> {
+        Ok(self.cursor)
+    }
+}
+
+/// A fixed list of events, as if typed by a user who never waits, but never types while the
+/// picker is still loading or searching either.
+///
+/// Events arrive for `poll` straight away, but not for `poll_busy`, so a picker waiting for
+/// keys while items or results are coming in always takes them all first, unless they're
+/// `hurried`. `idle` steps make one `poll` time out. Reading past the end of the script is an
+/// error, which closes the picker.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptedEvents(VecDeque<Step>);
+
+#[derive(Clone, Debug)]
+enum Step {
+    Event(Event),
+    // arrives for `poll_busy` too
+    Hurried(Event),
+    Idle,
+}
+
+impl ScriptedEvents {
+    pub fn new() -> ScriptedEvents {
+        ScriptedEvents::default()
+    }
+
+    pub fn event(mut self, event: Event) -> ScriptedEvents {
+        self.0.push_back(Step::Event(event));
+        self
+    }
+
+    /// The events of `events`, made to arrive even while the picker is busy, as if typed while
+    /// it loads or searches.
+    pub fn hurried(mut self, events: ScriptedEvents) -> ScriptedEvents {
+        self.0.extend(events.0.into_iter().map(|step| match step {
+            Step::Event(event) | Step::Hurried(event) => Step::Hurried(event),
+            Step::Idle => Step::Idle,
+        }));
+        self
+    }
+
+    pub fn key(self, code: KeyCode) -> ScriptedEvents {
+        self.key_with(code, KeyModifiers::empty())
+    }
+
+    pub fn key_with(self, code: KeyCode, modifiers: KeyModifiers) -> ScriptedEvents {
+        self.event(Event::Key(KeyEvent::new(code, modifiers)))
+    }
+
+    /// Type each char of `text`.
+    pub fn text(self, text: &str) -> ScriptedEvents {
+        text.chars()
+            .fold(self, |events, c| events.key(KeyCode::Char(c)))
+    }
+
+    /// Left click at a column and row of the screen.
+    pub fn click(self, column: u16, row: u16) -> ScriptedEvents {
+        self.event(Event::Mouse(MouseEvent::Down(
+            MouseButton::Left,
+            column,
+            row,
+            KeyModifiers::empty(),
+        )))
+    }
+
+    pub fn idle(mut self) -> ScriptedEvents {
+        self.0.push_back(Step::Idle);
+        self
+    }
+
+    /// Whether every event has been read.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Events for ScriptedEvents {
+    fn poll(&mut self, _timeout: Duration) -> Result<bool> {
+        match self.0.front() {
+            Some(Step::Idle) => {
+                self.0.pop_front();
+                Ok(false)
+            }
+            _ => Ok(true),
+        }
+    }
+
+    fn poll_busy(&mut self, timeout: Duration) -> Result<bool> {
+        match self.0.front() {
+            Some(Step::Hurried(_)) => Ok(true),
+            _ => {
+                // as if nothing was typed while waiting
+                thread::sleep(timeout);
+                Ok(false)
+            }
+        }
+    }
+
+    fn read(&mut self) -> Result<Event> {
+        match self.0.pop_front() {
+            Some(Step::Event(event)) | Some(Step::Hurried(event)) => Ok(event),
+            _ => Err(ErrorKind::IoError(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "no more scripted events",
+            ))),
+        }
+    }
+}
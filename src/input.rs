@@ -0,0 +1,176 @@
+use std::{
+    io,
+    sync::mpsc::{channel, Receiver, Sender, TryRecvError},
+    thread::Scope,
+    time::Duration,
+};
+
+use crossterm::{event::Event, ErrorKind, Result};
+
+use crate::backend::Events;
+use crate::waker::Waker;
+
+// Longest the input thread waits for an event at a time while the picker is idle, before
+// checking that the picker is still open.
+const POLL: Duration = Duration::from_millis(250);
+
+/// Reads events on a thread of its own, waking the picker as each arrives, so a key ends its
+/// wait for items or results straight away.
+///
+/// Events are only read as the picker asks for them, one at a time, so none are taken that it
+/// doesn't get to.
+pub(crate) struct Input {
+    requests: Sender<Option<Duration>>,
+    events: Receiver<Result<Option<Event>>>,
+    // whether an event was asked for that hasn't come, and whose wait hasn't timed out
+    asked: bool,
+}
+
+impl Input {
+    /// Read `events` on a thread of `scope`, waking `waker` as each is read.
+    pub(crate) fn spawn<'scope, 'env, E>(
+        scope: &'scope Scope<'scope, 'env>,
+        events: &'env mut E,
+        waker: Waker,
+    ) -> Input
+    where
+        E: Events,
+    {
+        let (requests, requested) = channel();
+        let (read, received) = channel();
+        scope.spawn(move || {
+            while let Ok(timeout) = requested.recv() {
+                let event = match next(events, timeout, &requested) {
+                    Some(event) => event,
+                    None => break,
+                };
+                if read.send(event).is_err() {
+                    break;
+                }
+                waker.wake();
+            }
+        });
+        Input {
+            requests,
+            events: received,
+            asked: false,
+        }
+    }
+
+    /// Ask for the next event, unless it already has been, waiting up to `timeout` for it
+    /// while the picker is busy, or for as long as it takes otherwise.
+    pub(crate) fn request(&mut self, timeout: Option<Duration>) {
+        if !self.asked {
+            // the thread only stops once this is dropped
+            let _ = self.requests.send(timeout);
+            self.asked = true;
+        }
+    }
+
+    /// The event asked for, once it's read.
+    pub(crate) fn receive(&mut self) -> Result<Option<Event>> {
+        match self.events.try_recv() {
+            Ok(event) => {
+                self.asked = false;
+                event
+            }
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(ErrorKind::IoError(io::Error::other(
+                "reading events panicked",
+            ))),
+        }
+    }
+}
+
+// The next of `events`, or `Ok(None)` if a wait while busy timed out first. Nothing comes once
+// the picker has closed.
+fn next<E>(
+    events: &mut E,
+    timeout: Option<Duration>,
+    requests: &Receiver<Option<Duration>>,
+) -> Option<Result<Option<Event>>>
+where
+    E: Events,
+{
+    loop {
+        let ready = match timeout {
+            Some(timeout) => events.poll_busy(timeout),
+            None => events.poll(POLL),
+        };
+        match ready {
+            Ok(true) => return Some(events.read().map(Some)),
+            Ok(false) if timeout.is_some() => return Some(Ok(None)),
+            Ok(false) => {
+                if let Err(TryRecvError::Disconnected) = requests.try_recv() {
+                    return None;
+                }
+            }
+            Err(e) => return Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crossterm::event::{KeyCode, KeyEvent};
+
+    use super::*;
+
+    // Keys typed whenever the test sends them.
+    struct Typed {
+        keys: Receiver<Event>,
+        next: Option<Event>,
+    }
+
+    impl Events for Typed {
+        fn poll(&mut self, timeout: Duration) -> Result<bool> {
+            if self.next.is_none() {
+                self.next = self.keys.recv_timeout(timeout).ok();
+            }
+            Ok(self.next.is_some())
+        }
+
+        fn read(&mut self) -> Result<Event> {
+            Ok(self.next.take().unwrap())
+        }
+    }
+
+    #[test]
+    fn a_key_ends_a_wait_straight_away() {
+        let (typing, keys) = channel();
+        let mut events = Typed { keys, next: None };
+        let waker = Waker::default();
+        thread::scope(|scope| {
+            let mut input = Input::spawn(scope, &mut events, waker.clone());
+            input.request(Some(Duration::from_secs(60)));
+            assert_eq!(input.receive().unwrap(), None);
+
+            let start = Instant::now();
+            let esc = Event::Key(KeyEvent::from(KeyCode::Esc));
+            typing.send(esc).unwrap();
+            waker.wait(start + Duration::from_secs(60));
+            assert!(start.elapsed() < Duration::from_secs(5));
+            assert_eq!(input.receive().unwrap(), Some(esc));
+        });
+    }
+
+    #[test]
+    fn a_wait_while_busy_times_out() {
+        let (_typing, keys) = channel();
+        let mut events = Typed { keys, next: None };
+        let waker = Waker::default();
+        thread::scope(|scope| {
+            let mut input = Input::spawn(scope, &mut events, waker.clone());
+            input.request(Some(Duration::from_millis(10)));
+            waker.wait(Instant::now() + Duration::from_secs(60));
+            assert_eq!(input.receive().unwrap(), None);
+            // and the next key can be asked for
+            assert!(!input.asked);
+        });
+    }
+}
@@ -0,0 +1,111 @@
+use std::{borrow::Cow, fmt::Display, sync::Arc};
+
+use crossterm::style::{ContentStyle, StyledContent};
+
+/// Something that can be picked.
+///
+/// Strings, chars and numbers are items searched and drawn as themselves, and any other type
+/// that implements `Display` can be wrapped in `Plain` to be searched and drawn as its
+/// `to_string()`. Types can implement `Item` themselves to search on some text but draw other,
+/// styled text, whether or not they implement `Display`:
+///
+/// ```no_run
+/// use std::borrow::Cow;
+///
+/// use picky::{Color, ContentStyle, Item, StyledContent};
+///
+/// #[derive(Clone)]
+/// struct Process {
+///     pid: u32,
+///     name: String,
+///     memory: u64,
+/// }
+///
+/// impl Item for Process {
+///     fn search_text(&self) -> Cow<str> {
+///         format!("{} {}", self.name, self.pid).into()
+///     }
+///
+///     fn display(&self) -> Vec<StyledContent<String>> {
+///         vec![
+///             ContentStyle::new().foreground(Color::Blue).apply(format!("{:>7} ", self.pid)),
+///             ContentStyle::new().apply(format!("{:<20} {:>8}K", self.name, self.memory)),
+///         ]
+///     }
+/// }
+/// ```
+pub trait Item: Clone + Send + Sync {
+    /// The text the query is matched against.
+    fn search_text(&self) -> Cow<'_, str>;
+
+    /// The text drawn in the results. Matched characters are only highlighted when this is the
+    /// same text as `search_text`.
+    fn display(&self) -> Vec<StyledContent<String>> {
+        vec![ContentStyle::new().apply(self.search_text().into_owned())]
+    }
+
+    /// Text shown next to the results by `Picker::item_preview`.
+    fn preview(&self) -> Option<String> {
+        None
+    }
+}
+
+/// An item searched and drawn as the `to_string()` of what it wraps.
+///
+/// ```no_run
+/// use std::net::Ipv4Addr;
+///
+/// use picky::{Picker, Plain};
+///
+/// let addresses = [Ipv4Addr::LOCALHOST, Ipv4Addr::BROADCAST].map(Plain);
+/// let picked = Picker::new().run(&addresses).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Plain<T>(pub T);
+
+impl<T> Item for Plain<T>
+where
+    T: Display + Clone + Send + Sync,
+{
+    fn search_text(&self) -> Cow<'_, str> {
+        self.0.to_string().into()
+    }
+}
+
+impl Item for String {
+    fn search_text(&self) -> Cow<'_, str> {
+        self.as_str().into()
+    }
+}
+
+impl Item for &str {
+    fn search_text(&self) -> Cow<'_, str> {
+        (*self).into()
+    }
+}
+
+impl Item for Cow<'_, str> {
+    fn search_text(&self) -> Cow<'_, str> {
+        self.as_ref().into()
+    }
+}
+
+impl Item for Arc<str> {
+    fn search_text(&self) -> Cow<'_, str> {
+        self.as_ref().into()
+    }
+}
+
+macro_rules! displayed {
+    ($($t:ty),*) => {
+        $(
+            impl Item for $t {
+                fn search_text(&self) -> Cow<'_, str> {
+                    self.to_string().into()
+                }
+            }
+        )*
+    };
+}
+
+displayed!(char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
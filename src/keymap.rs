@@ -0,0 +1,447 @@
+use std::{collections::HashMap, error::Error, fmt, str::FromStr};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Something the picker can do in response to a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Accept,
+    Abort,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    /// Jump to the best result. Bound to ctrl-home, as home moves the query cursor first.
+    First,
+    /// Jump to the last result. Bound to ctrl-end, as end moves the query cursor first.
+    Last,
+    /// Mark or unmark the highlighted item in multi-select mode.
+    Toggle,
+    ToggleUp,
+    ToggleDown,
+    SelectAll,
+    DeselectAll,
+    ToggleAll,
+    ClearQuery,
+    BackwardChar,
+    ForwardChar,
+    BeginningOfLine,
+    EndOfLine,
+    /// Move the cursor to the start of the query, or jump to the best result if it's there
+    /// already. Bound to home.
+    BeginningOfLineOrFirst,
+    /// Move the cursor to the end of the query, or jump to the last result if it's there
+    /// already. Bound to end.
+    EndOfLineOrLast,
+    BackwardWord,
+    ForwardWord,
+    BackwardDeleteChar,
+    DeleteChar,
+    BackwardKillWord,
+    KillWord,
+    /// Delete the whitespace separated word before the cursor.
+    UnixWordRubout,
+    /// Delete from the cursor to the start of the query.
+    UnixLineDiscard,
+    /// Delete from the cursor to the end of the query.
+    KillLine,
+    /// Insert the text most recently deleted by a kill action.
+    Yank,
+    TogglePreview,
+    PreviewUp,
+    PreviewDown,
+    /// Do nothing, for unbinding a default key.
+    Ignore,
+}
+
+const ACTION_NAMES: &[(&str, Action)] = &[
+    ("accept", Action::Accept),
+    ("abort", Action::Abort),
+    ("up", Action::Up),
+    ("down", Action::Down),
+    ("page-up", Action::PageUp),
+    ("page-down", Action::PageDown),
+    ("first", Action::First),
+    ("last", Action::Last),
+    ("toggle", Action::Toggle),
+    ("toggle+up", Action::ToggleUp),
+    ("toggle+down", Action::ToggleDown),
+    ("select-all", Action::SelectAll),
+    ("deselect-all", Action::DeselectAll),
+    ("toggle-all", Action::ToggleAll),
+    ("clear-query", Action::ClearQuery),
+    ("backward-char", Action::BackwardChar),
+    ("forward-char", Action::ForwardChar),
+    ("beginning-of-line", Action::BeginningOfLine),
+    ("end-of-line", Action::EndOfLine),
+    ("beginning-of-line-or-first", Action::BeginningOfLineOrFirst),
+    ("end-of-line-or-last", Action::EndOfLineOrLast),
+    ("backward-word", Action::BackwardWord),
+    ("forward-word", Action::ForwardWord),
+    ("backward-delete-char", Action::BackwardDeleteChar),
+    ("delete-char", Action::DeleteChar),
+    ("backward-kill-word", Action::BackwardKillWord),
+    ("kill-word", Action::KillWord),
+    ("unix-word-rubout", Action::UnixWordRubout),
+    ("unix-line-discard", Action::UnixLineDiscard),
+    ("kill-line", Action::KillLine),
+    ("yank", Action::Yank),
+    ("toggle-preview", Action::TogglePreview),
+    ("preview-up", Action::PreviewUp),
+    ("preview-down", Action::PreviewDown),
+    ("ignore", Action::Ignore),
+];
+
+impl Action {
+    /// Whether the action edits the query or moves the cursor within it.
+    pub fn edits(self) -> bool {
+        matches!(
+            self,
+            Action::ClearQuery
+                | Action::BackwardChar
+                | Action::ForwardChar
+                | Action::BeginningOfLine
+                | Action::EndOfLine
+                | Action::BackwardWord
+                | Action::ForwardWord
+                | Action::BackwardDeleteChar
+                | Action::DeleteChar
+                | Action::BackwardKillWord
+                | Action::KillWord
+                | Action::UnixWordRubout
+                | Action::UnixLineDiscard
+                | Action::KillLine
+                | Action::Yank
+        )
+    }
+}
+
+impl FromStr for Action {
+    type Err = ParseError;
+
+    /// Parse the fzf style name of an action, such as `accept` or `toggle+down`.
+    fn from_str(s: &str) -> Result<Action, ParseError> {
+        ACTION_NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, action)| *action)
+            .ok_or_else(|| ParseError(format!("unknown action: {}", s)))
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = ACTION_NAMES
+            .iter()
+            .find(|(_, action)| action == self)
+            .map(|(name, _)| *name)
+            .unwrap_or_default();
+        f.write_str(name)
+    }
+}
+
+/// A key or action name that couldn't be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError(pub(crate) String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parse a key such as `enter`, `ctrl-a`, `alt-shift-up` or `f5`.
+pub fn parse_key(s: &str) -> Result<KeyEvent, ParseError> {
+    let error = || ParseError(format!("unknown key: {}", s));
+
+    let mut modifiers = KeyModifiers::empty();
+    let mut rest = s;
+    while let Some((modifier, len)) = modifier(rest) {
+        modifiers |= modifier;
+        rest = &rest[len..];
+    }
+
+    let code = match rest.to_ascii_lowercase().as_str() {
+        "enter" | "return" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "btab" | "backtab" => KeyCode::BackTab,
+        "bspace" | "backspace" => KeyCode::Backspace,
+        "del" | "delete" => KeyCode::Delete,
+        "ins" | "insert" => KeyCode::Insert,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pgup" | "page-up" => KeyCode::PageUp,
+        "pgdn" | "page-down" => KeyCode::PageDown,
+        "space" => KeyCode::Char(' '),
+        f if f.len() > 1 && f.starts_with('f') => KeyCode::F(f[1..].parse().map_err(|_| error())?),
+        _ => {
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                // terminals report ctrl-letters in lowercase
+                (Some(c), None) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    KeyCode::Char(c.to_ascii_lowercase())
+                }
+                (Some(c), None) => KeyCode::Char(c),
+                _ => return Err(error()),
+            }
+        }
+    };
+
+    Ok(KeyEvent::new(code, modifiers))
+}
+
+// The modifier `s` starts with, and how long its prefix is.
+fn modifier(s: &str) -> Option<(KeyModifiers, usize)> {
+    let lower = s.get(..6).unwrap_or(s).to_ascii_lowercase();
+    if lower.starts_with("ctrl-") {
+        Some((KeyModifiers::CONTROL, 5))
+    } else if lower.starts_with("alt-") {
+        Some((KeyModifiers::ALT, 4))
+    } else if lower.starts_with("shift-") {
+        Some((KeyModifiers::SHIFT, 6))
+    } else {
+        None
+    }
+}
+
+/// Parse keys separated by commas, such as `ctrl-x,alt-enter`, where a key may itself be `,`.
+pub fn parse_keys(s: &str) -> Result<Vec<KeyEvent>, ParseError> {
+    let mut keys = Vec::new();
+    let mut rest = s;
+    while !rest.is_empty() {
+        let (key, next) =
+            split_key(rest, ',').ok_or_else(|| ParseError(format!("unknown key: {}", rest)))?;
+        keys.push(parse_key(key)?);
+        rest = next.unwrap_or_default();
+    }
+    Ok(keys)
+}
+
+// Split the key off the start of `s` at the next `separator`, returning what follows it if
+// there is one. The key may itself be `,` or `:`, so it's at least a char after its modifiers.
+fn split_key(s: &str, separator: char) -> Option<(&str, Option<&str>)> {
+    let mut start = 0;
+    while let Some((_, len)) = modifier(&s[start..]) {
+        start += len;
+    }
+    start += s[start..].chars().next()?.len_utf8();
+    Some(match s[start..].find(separator) {
+        Some(i) => (&s[..start + i], Some(&s[start + i + 1..])),
+        None => (s, None),
+    })
+}
+
+/// Name `key` the way `parse_key` reads it, such as `ctrl-a` or `alt-enter`.
+pub fn key_name(key: KeyEvent) -> String {
+    let mut name = String::new();
+    for (modifier, prefix) in &[
+        (KeyModifiers::CONTROL, "ctrl-"),
+        (KeyModifiers::ALT, "alt-"),
+        (KeyModifiers::SHIFT, "shift-"),
+    ] {
+        if key.modifiers.contains(*modifier) {
+            name.push_str(prefix);
+        }
+    }
+    match key.code {
+        KeyCode::Enter => name.push_str("enter"),
+        KeyCode::Esc => name.push_str("esc"),
+        KeyCode::Tab => name.push_str("tab"),
+        KeyCode::BackTab => name.push_str("btab"),
+        KeyCode::Backspace => name.push_str("bspace"),
+        KeyCode::Delete => name.push_str("del"),
+        KeyCode::Insert => name.push_str("ins"),
+        KeyCode::Up => name.push_str("up"),
+        KeyCode::Down => name.push_str("down"),
+        KeyCode::Left => name.push_str("left"),
+        KeyCode::Right => name.push_str("right"),
+        KeyCode::Home => name.push_str("home"),
+        KeyCode::End => name.push_str("end"),
+        KeyCode::PageUp => name.push_str("pgup"),
+        KeyCode::PageDown => name.push_str("pgdn"),
+        KeyCode::Char(' ') => name.push_str("space"),
+        KeyCode::Char(c) => name.push(c),
+        KeyCode::F(n) => name.push_str(&format!("f{}", n)),
+        KeyCode::Null => name.push_str("null"),
+    }
+    name
+}
+
+/// Key bindings, starting from the defaults.
+#[derive(Clone, Debug)]
+pub struct KeyMap(HashMap<KeyEvent, Action>);
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        let none = KeyModifiers::empty();
+        let ctrl = KeyModifiers::CONTROL;
+        let alt = KeyModifiers::ALT;
+        let shift = KeyModifiers::SHIFT;
+
+        let bindings = vec![
+            (KeyCode::Enter, none, Action::Accept),
+            (KeyCode::Esc, none, Action::Abort),
+            (KeyCode::Char('c'), ctrl, Action::Abort),
+            (KeyCode::Up, none, Action::Up),
+            (KeyCode::Char('p'), ctrl, Action::Up),
+            (KeyCode::Down, none, Action::Down),
+            (KeyCode::Char('n'), ctrl, Action::Down),
+            (KeyCode::PageUp, none, Action::PageUp),
+            (KeyCode::PageDown, none, Action::PageDown),
+            (KeyCode::Home, ctrl, Action::First),
+            (KeyCode::End, ctrl, Action::Last),
+            (KeyCode::Tab, none, Action::ToggleDown),
+            (KeyCode::BackTab, none, Action::ToggleUp),
+            (KeyCode::BackTab, shift, Action::ToggleUp),
+            (KeyCode::Char('a'), alt, Action::SelectAll),
+            (KeyCode::Char('d'), alt, Action::DeselectAll),
+            (KeyCode::Char('t'), alt, Action::ToggleAll),
+            (KeyCode::Left, none, Action::BackwardChar),
+            (KeyCode::Char('b'), ctrl, Action::BackwardChar),
+            (KeyCode::Right, none, Action::ForwardChar),
+            (KeyCode::Char('f'), ctrl, Action::ForwardChar),
+            (KeyCode::Home, none, Action::BeginningOfLineOrFirst),
+            (KeyCode::Char('a'), ctrl, Action::BeginningOfLine),
+            (KeyCode::End, none, Action::EndOfLineOrLast),
+            (KeyCode::Char('e'), ctrl, Action::EndOfLine),
+            (KeyCode::Char('b'), alt, Action::BackwardWord),
+            (KeyCode::Char('f'), alt, Action::ForwardWord),
+            (KeyCode::Backspace, none, Action::BackwardDeleteChar),
+            (KeyCode::Char('h'), ctrl, Action::BackwardDeleteChar),
+            (KeyCode::Delete, none, Action::DeleteChar),
+            (KeyCode::Backspace, alt, Action::BackwardKillWord),
+            (KeyCode::Char('w'), ctrl, Action::UnixWordRubout),
+            (KeyCode::Char('u'), ctrl, Action::UnixLineDiscard),
+            (KeyCode::Char('k'), ctrl, Action::KillLine),
+            (KeyCode::Char('y'), ctrl, Action::Yank),
+            (KeyCode::Char('p'), alt, Action::TogglePreview),
+            (KeyCode::Up, shift, Action::PreviewUp),
+            (KeyCode::Down, shift, Action::PreviewDown),
+        ];
+
+        KeyMap(
+            bindings
+                .into_iter()
+                .map(|(code, modifiers, action)| (KeyEvent::new(code, modifiers), action))
+                .collect(),
+        )
+    }
+}
+
+impl KeyMap {
+    /// A key map with nothing bound, where keys only type into the query.
+    pub fn empty() -> KeyMap {
+        KeyMap(HashMap::new())
+    }
+
+    pub fn bind(&mut self, key: KeyEvent, action: Action) {
+        self.0.insert(key, action);
+    }
+
+    pub fn unbind(&mut self, key: KeyEvent) {
+        self.0.remove(&key);
+    }
+
+    pub fn action(&self, key: KeyEvent) -> Option<Action> {
+        self.0.get(&key).copied()
+    }
+
+    /// Apply bindings written as `key:action`, separated by commas, such as
+    /// `ctrl-j:down,ctrl-k:up`.
+    pub fn parse_bindings(&mut self, bindings: &str) -> Result<(), ParseError> {
+        let mut rest = bindings;
+        while !rest.is_empty() {
+            let error = || ParseError(format!("expected key:action, got {}", rest));
+            let (key, action) = match split_key(rest, ':') {
+                Some((key, Some(action))) => (parse_key(key)?, action),
+                _ => return Err(error()),
+            };
+            let (action, next) = action.split_once(',').unwrap_or((action, ""));
+            self.bind(key, action.parse()?);
+            rest = next;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parses_keys_with_modifiers() {
+        let (none, ctrl, alt) = (
+            KeyModifiers::empty(),
+            KeyModifiers::CONTROL,
+            KeyModifiers::ALT,
+        );
+        assert_eq!(parse_key("enter"), Ok(key(KeyCode::Enter, none)));
+        assert_eq!(parse_key("ctrl-a"), Ok(key(KeyCode::Char('a'), ctrl)));
+        assert_eq!(parse_key("Ctrl-A"), Ok(key(KeyCode::Char('a'), ctrl)));
+        assert_eq!(parse_key("A"), Ok(key(KeyCode::Char('A'), none)));
+        assert_eq!(
+            parse_key("alt-shift-up"),
+            Ok(key(KeyCode::Up, alt | KeyModifiers::SHIFT))
+        );
+        assert_eq!(parse_key("f5"), Ok(key(KeyCode::F(5), none)));
+        assert_eq!(parse_key("f"), Ok(key(KeyCode::Char('f'), none)));
+        assert_eq!(parse_key("alt-:"), Ok(key(KeyCode::Char(':'), alt)));
+        assert_eq!(parse_key("-"), Ok(key(KeyCode::Char('-'), none)));
+        assert!(parse_key("").is_err());
+        assert!(parse_key("ctrl-").is_err());
+        assert!(parse_key("hyper-a").is_err());
+        assert!(parse_key("fx").is_err());
+    }
+
+    #[test]
+    fn parses_lists_of_keys() {
+        let keys = parse_keys("ctrl-x,,,alt-,,enter").unwrap();
+        let names: Vec<_> = keys.into_iter().map(key_name).collect();
+        assert_eq!(names, ["ctrl-x", ",", "alt-,", "enter"]);
+        assert!(parse_keys("ctrl-x,nokey").is_err());
+        assert!(parse_keys("ctrl-").is_err());
+    }
+
+    #[test]
+    fn names_keys_as_they_are_parsed() {
+        for name in &["enter", "ctrl-a", "alt-shift-up", "f12", "space", ",", ":"] {
+            assert_eq!(key_name(parse_key(name).unwrap()), *name);
+        }
+    }
+
+    #[test]
+    fn parses_bindings() {
+        let mut keymap = KeyMap::empty();
+        keymap
+            .parse_bindings("ctrl-j:down,alt-::toggle,::up,,:first,alt-,:last")
+            .unwrap();
+        let action = |k| keymap.action(parse_key(k).unwrap());
+        assert_eq!(action("ctrl-j"), Some(Action::Down));
+        assert_eq!(action("alt-:"), Some(Action::Toggle));
+        assert_eq!(action(":"), Some(Action::Up));
+        assert_eq!(action(","), Some(Action::First));
+        assert_eq!(action("alt-,"), Some(Action::Last));
+        assert_eq!(action("enter"), None);
+    }
+
+    #[test]
+    fn rejects_bad_bindings() {
+        let mut keymap = KeyMap::default();
+        assert!(keymap.parse_bindings("ctrl-j").is_err());
+        assert!(keymap.parse_bindings(":up").is_err());
+        assert!(keymap.parse_bindings("ctrl-j:sideways").is_err());
+        assert!(keymap.parse_bindings("nokey:up").is_err());
+        assert!(keymap.parse_bindings("ctrl-:up").is_err());
+    }
+}
@@ -1,31 +1,297 @@
 use std::{
-    cmp::Ordering,
-    collections::BinaryHeap,
-    collections::HashMap,
-    fmt::{Debug, Display},
-    io::{stdout, Write},
-    sync::Arc,
+    collections::{BTreeSet, HashMap},
+    fmt::Debug,
+    io::Write,
+    sync::{
+        mpsc::{Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
     time::{Duration, Instant},
 };
 
-use crossterm::{cursor::*, event::*, execute, queue, style::*, terminal::*, Result};
-use fuzzy_matcher::skim::SkimMatcherV2;
-use fuzzy_matcher::FuzzyMatcher;
-use rand::Rng;
-use rayon::prelude::*;
+use crossterm::{cursor::*, event::*, queue, style::*, terminal::*, Result};
 
-pub trait Item: Display + Clone + Send + Sync {}
-impl<T: Display + Clone + Send + Sync> Item for T {}
+mod backend;
+mod fields;
+#[cfg(feature = "async")]
+mod future;
+mod headless;
+mod input;
+mod item;
+mod keymap;
+pub mod matcher;
+mod outcome;
+mod picker;
+mod preview;
+mod query;
+mod regex;
+mod search;
+mod source;
+mod theme;
+mod tiebreak;
+mod waker;
+mod width;
+mod worker;
+
+pub use backend::{Backend, CrosstermBackend, CrosstermEvents, Events, Terminal};
+pub use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+pub use crossterm::style::{Attribute, Color, ContentStyle, StyledContent};
+pub use fields::{Delimiter, Fields};
+#[cfg(feature = "async")]
+pub use future::Selection;
+pub use headless::{ScriptedEvents, TestBackend};
+pub use item::{Item, Plain};
+pub use keymap::{key_name, parse_key, parse_keys, Action, KeyMap, ParseError};
+pub use matcher::{Algorithm, CaseMatching, Matcher};
+pub use outcome::Outcome;
+pub use picker::Picker;
+pub use preview::{PreviewPosition, PreviewSize, PreviewWindow};
+pub use source::Source;
+pub use theme::Theme;
+pub use tiebreak::Tiebreak;
+
+use fields::FieldOptions;
+use input::Input;
+use preview::{Preview, Previewer};
+use search::{Search, SearchKey};
+use theme::layer;
+use waker::Waker;
+
+type PreviewFn<'a, T> = dyn Fn(&T) -> String + Send + Sync + 'a;
 
 #[derive(Clone, Debug)]
 struct Prompt {
     prompt: String,
     text: String,
+    // position in `text`, in chars
+    cursor: usize,
+    // text removed by the last kill, for yanking back
+    yank: String,
     header: Option<String>,
+    // screen row the picker is drawn from
+    row: usize,
+    // result rows asked for, and how many fit the terminal
+    rows: usize,
     height: usize,
     width: usize,
+    // index of the highlighted result, and of the first result in view
     selection: usize,
+    offset: usize,
+    // number of results for the current query
+    matches: usize,
     color_map: HashMap<char, Color>,
+    multi: bool,
+    max_selections: Option<usize>,
+    marked: BTreeSet<usize>,
+    loading: bool,
+    loaded: usize,
+    // items searched for the query so far, out of how many, while a search runs
+    progress: Option<(usize, usize)>,
+    spinner: usize,
+    preview: Option<Preview>,
+    keymap: KeyMap,
+    theme: Theme,
+    fields: FieldOptions,
+    tiebreaks: Vec<Tiebreak>,
+}
+
+impl Prompt {
+    // The preview, unless it's toggled off.
+    fn shown_preview(&self) -> Option<&Preview> {
+        self.preview.as_ref().filter(|preview| preview.visible)
+    }
+
+    // Columns left for the results beside a preview.
+    fn list_width(&self) -> usize {
+        match self.shown_preview() {
+            Some(preview) => self.width - preview.window.columns(self.width),
+            None => self.width,
+        }
+    }
+
+    // Rows for the results, which take over those of a hidden preview.
+    fn list_rows(&self) -> usize {
+        match &self.preview {
+            Some(preview) if !preview.visible => self.height + preview.window.rows(self.height),
+            _ => self.height,
+        }
+    }
+
+    fn preview_rows(&self, position: PreviewPosition) -> usize {
+        match self.shown_preview() {
+            Some(preview) if preview.window.position == position => {
+                preview.window.rows(self.height)
+            }
+            _ => 0,
+        }
+    }
+
+    // Fit a resized terminal, with as many of the result rows asked for as fit below `row`.
+    fn resize(&mut self, width: u16, height: u16) {
+        self.width = width as usize;
+        let header_rows = self.header.is_some() as usize;
+        let free = (height as usize).saturating_sub(self.row + 1 + header_rows);
+        let preview_rows = |rows| self.preview.as_ref().map_or(0, |p| p.window.rows(rows));
+        self.height = (1..=self.rows)
+            .rev()
+            .find(|&rows| rows + preview_rows(rows) <= free)
+            .unwrap_or(1);
+    }
+
+    // Byte offset in `text` of the char at `cursor`.
+    fn byte_offset(&self, cursor: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
+
+    fn insert(&mut self, text: &str) {
+        let offset = self.byte_offset(self.cursor);
+        self.text.insert_str(offset, text);
+        self.cursor += text.chars().count();
+    }
+
+    // Remove the chars between `start` and `end`, keeping them for yanking if `kill` is set.
+    fn remove(&mut self, start: usize, end: usize, kill: bool) -> bool {
+        let (start, end) = (self.byte_offset(start), self.byte_offset(end));
+        if start == end {
+            return false;
+        }
+        let removed: String = self.text.drain(start..end).collect();
+        if kill {
+            self.yank = removed;
+        }
+        self.cursor = self.text[..start].chars().count();
+        true
+    }
+
+    // Start of the word before the cursor, where words are separated by anything for which
+    // `separator` is true.
+    fn word_start(&self, separator: impl Fn(char) -> bool) -> usize {
+        let chars: Vec<_> = self.text.chars().take(self.cursor).collect();
+        let mut start = chars.len();
+        while start > 0 && separator(chars[start - 1]) {
+            start -= 1;
+        }
+        while start > 0 && !separator(chars[start - 1]) {
+            start -= 1;
+        }
+        start
+    }
+
+    // Start of the grapheme before the cursor, so combined characters move and delete as one.
+    fn previous_boundary(&self) -> usize {
+        width::graphemes(&self.text)
+            .iter()
+            .map(|g| g.start)
+            .take_while(|&start| start < self.cursor)
+            .last()
+            .unwrap_or(0)
+    }
+
+    fn next_boundary(&self) -> usize {
+        width::graphemes(&self.text)
+            .iter()
+            .map(|g| g.start + g.len)
+            .find(|&end| end > self.cursor)
+            .unwrap_or(self.cursor)
+    }
+
+    fn word_end(&self) -> usize {
+        let mut chars = self.text.chars().skip(self.cursor).peekable();
+        let mut end = self.cursor;
+        while chars.next_if(|c| !c.is_alphanumeric()).is_some() {
+            end += 1;
+        }
+        while chars.next_if(|c| c.is_alphanumeric()).is_some() {
+            end += 1;
+        }
+        end
+    }
+
+    // Apply an editing action to the query, returning whether the text changed.
+    fn edit(&mut self, action: Action) -> bool {
+        let len = self.text.chars().count();
+        let word = |c: char| !c.is_alphanumeric();
+        match action {
+            Action::ClearQuery => self.remove(0, len, false),
+            Action::BackwardChar => {
+                self.cursor = self.previous_boundary();
+                false
+            }
+            Action::ForwardChar => {
+                self.cursor = self.next_boundary();
+                false
+            }
+            Action::BeginningOfLine => {
+                self.cursor = 0;
+                false
+            }
+            Action::EndOfLine => {
+                self.cursor = len;
+                false
+            }
+            Action::BackwardWord => {
+                self.cursor = self.word_start(word);
+                false
+            }
+            Action::ForwardWord => {
+                self.cursor = self.word_end();
+                false
+            }
+            Action::BackwardDeleteChar => self.remove(self.previous_boundary(), self.cursor, false),
+            Action::DeleteChar => self.remove(self.cursor, self.next_boundary(), false),
+            Action::BackwardKillWord => self.remove(self.word_start(word), self.cursor, true),
+            Action::KillWord => self.remove(self.cursor, self.word_end(), true),
+            Action::UnixWordRubout => {
+                self.remove(self.word_start(char::is_whitespace), self.cursor, true)
+            }
+            Action::UnixLineDiscard => self.remove(0, self.cursor, true),
+            Action::KillLine => self.remove(self.cursor, len, true),
+            Action::Yank if !self.yank.is_empty() => {
+                self.insert(&self.yank.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Index of the result drawn at a screen position.
+    fn result_at(&self, column: u16, row: u16) -> Option<usize> {
+        let header_rows = self.header.is_some() as usize;
+        let first = self.row + self.preview_rows(PreviewPosition::Top) + 1 + header_rows;
+        let y = (row as usize).checked_sub(first)?;
+        if y >= self.list_rows() || column as usize >= self.list_width() {
+            return None;
+        }
+        let index = self.offset + y;
+        (index < self.matches).then_some(index)
+    }
+
+    // `style` in the color picked for the letter `c`, if letters have colors.
+    fn letter_color(&self, style: &ContentStyle, c: char) -> ContentStyle {
+        match self.color_map.get(&c) {
+            Some(&color) => style.clone().foreground(color),
+            None => style.clone(),
+        }
+    }
+
+    fn toggle_mark(&mut self, index: usize) {
+        if !self.marked.remove(&index) {
+            self.mark(index);
+        }
+    }
+
+    fn mark(&mut self, index: usize) {
+        if self
+            .max_selections
+            .is_none_or(|max| self.marked.len() < max)
+        {
+            self.marked.insert(index);
+        }
+    }
 }
 
 impl Default for Prompt {
@@ -33,92 +299,207 @@ impl Default for Prompt {
         Prompt {
             prompt: "> ".to_string(),
             text: "".to_string(),
+            cursor: 0,
+            yank: "".to_string(),
             header: None,
+            row: 0,
             width: 20,
+            rows: 5,
             height: 5,
             selection: 0,
+            offset: 0,
+            matches: 0,
             color_map: HashMap::new(),
+            multi: false,
+            max_selections: None,
+            marked: BTreeSet::new(),
+            loading: false,
+            loaded: 0,
+            progress: None,
+            spinner: 0,
+            preview: None,
+            keymap: KeyMap::default(),
+            theme: Theme::default(),
+            fields: FieldOptions::default(),
+            tiebreaks: vec![Tiebreak::Length],
         }
     }
 }
 
+const SPINNER: [char; 4] = ['-', '\\', '|', '/'];
+
+// Preview rows above or below the results, with a separator line next to the results.
+fn render_preview_rows<W>(prompt: &Prompt, write: &mut W, position: PreviewPosition) -> Result<()>
+where
+    W: Write,
+{
+    let rows = prompt.preview_rows(position);
+    let preview = match &prompt.preview {
+        Some(preview) if rows > 0 => preview,
+        _ => return Ok(()),
+    };
+
+    let separator = prompt
+        .theme
+        .border
+        .clone()
+        .apply("\u{2500}".repeat(prompt.width));
+    if position == PreviewPosition::Bottom {
+        queue!(
+            write,
+            MoveToNextLine(1),
+            Clear(ClearType::UntilNewLine),
+            Print(separator.clone())
+        )?;
+    }
+    for y in 0..rows - 1 {
+        if position == PreviewPosition::Bottom || y > 0 {
+            queue!(write, MoveToNextLine(1))?;
+        }
+        queue!(
+            write,
+            Clear(ClearType::UntilNewLine),
+            Print(preview.line(y, prompt.width))
+        )?;
+    }
+    if position == PreviewPosition::Top {
+        queue!(
+            write,
+            MoveToNextLine(1),
+            Clear(ClearType::UntilNewLine),
+            Print(separator),
+            MoveToNextLine(1)
+        )?;
+    }
+    Ok(())
+}
+
 fn render<W, T>(prompt: &Prompt, write: &mut W, items: &[RankedItem<T>]) -> Result<()>
 where
     W: Write,
     T: Item,
 {
+    let top_rows = prompt.preview_rows(PreviewPosition::Top);
+    let list_width = prompt.list_width();
+
+    let theme = &prompt.theme;
     let styled_prompt: Vec<_> = prompt
         .text
         .chars()
-        .map(|c| style(c).with(*prompt.color_map.get(&c).unwrap_or(&Color::White)))
+        .map(|c| prompt.letter_color(&theme.query, c).apply(c))
         .collect();
 
+    queue!(write, RestorePosition)?;
+    render_preview_rows(prompt, write, PreviewPosition::Top)?;
+
     queue!(
         write,
-        RestorePosition,
         Clear(ClearType::UntilNewLine),
-        Print(style(prompt.prompt.clone()).cyan().slow_blink().bold()),
+        Print(theme.prompt.clone().apply(&prompt.prompt)),
     )?;
 
     for style in styled_prompt {
         queue!(write, Print(style))?;
     }
 
-    queue!(write, MoveRight(prompt.text.len() as u16))?;
+    let info = match prompt.progress {
+        Some((searched, total)) => Some(format!("{}/{}", searched, total)),
+        None if prompt.loading => Some(prompt.loaded.to_string()),
+        None => None,
+    };
+    if let Some(info) = info {
+        let frame = SPINNER[prompt.spinner % SPINNER.len()];
+        queue!(
+            write,
+            Print(theme.info.clone().apply(format!("  {} {}", frame, info)))
+        )?;
+    }
 
     if let Some(header) = prompt.header.clone() {
-        let header_trimmed = if header.len() > prompt.width {
-            &header[..prompt.width - 3]
-        } else {
-            &header[..]
-        };
+        let header_trimmed = width::truncate(&header, prompt.width.saturating_sub(3));
         queue!(
             write,
             MoveToNextLine(1),
             Clear(ClearType::UntilNewLine),
             MoveRight(3),
-            Print(style(header_trimmed).dark_green()),
+            Print(theme.header.clone().apply(header_trimmed)),
         )?;
     }
 
-    for y in 0..prompt.height {
+    for y in 0..prompt.list_rows() {
         queue!(write, MoveToNextLine(1), Clear(ClearType::UntilNewLine))?;
         if y < items.len() {
             let to_print = &items.get(y).unwrap();
+            let selected = prompt.offset + y == prompt.selection;
 
-            let item_string = to_print.0.clone().to_string();
-            let to_display = if item_string.len() > prompt.width {
-                &item_string[..prompt.width - 3]
+            let number = (prompt.offset + y + 1).to_string();
+            let num = theme.number.clone().apply(&number);
+            let delim = if selected {
+                theme.pointer.clone().apply("> ")
             } else {
-                &item_string[..]
+                theme.number.clone().apply(": ")
             };
 
-            let matched_chars = &to_print.2;
-            let styled: Vec<_> = to_display
-                .chars()
-                .enumerate()
-                .map(|(i, c)| {
-                    let mut s = style(c);
-                    if y == prompt.selection {
-                        s = s.on_dark_grey();
-                    }
-                    if matched_chars.contains(&i) && to_print.1.is_some() {
-                        s = s
-                            .with(*prompt.color_map.get(&c).unwrap_or(&Color::White))
-                            .underlined()
-                            .bold()
-                            .italic();
+            // whole graphemes that fit after the marker, number and delimiter
+            let columns = list_width.saturating_sub(prompt.multi as usize + number.len() + 2);
+            let spans = to_print.item.display();
+            let item_string: String = spans.iter().map(|span| span.content().as_str()).collect();
+            // style of each char of the drawn text
+            let styles: Vec<_> = spans
+                .iter()
+                .flat_map(|span| span.content().chars().map(move |_| span.style()))
+                .collect();
+            // matches are positions in the search text, so only mark them if that's what is drawn
+            let highlight = to_print.score.is_some() && to_print.key.text == item_string;
+            let graphemes = width::graphemes(&item_string);
+            // only the fields picked to be shown, in the order they were picked
+            let graphemes = match prompt.fields.shown(&item_string) {
+                Some(shown) => shown
+                    .iter()
+                    .flat_map(|range| graphemes.iter().filter(move |g| range.contains(&g.start)))
+                    .copied()
+                    .collect(),
+                None => graphemes,
+            };
+            let mut used = 0;
+            let matched_chars = &to_print.indices;
+            let styled: Vec<_> = graphemes
+                .into_iter()
+                .take_while(|g| {
+                    used += g.width;
+                    used <= columns
+                })
+                .map(|g| {
+                    let base = if selected {
+                        theme.selected.clone()
+                    } else {
+                        ContentStyle::new()
+                    };
+                    let base = if theme.item_styles {
+                        layer(&base, styles[g.start])
+                    } else {
+                        base
+                    };
+                    let matched = (g.start..g.start + g.len).any(|i| matched_chars.contains(&i));
+                    if matched && highlight {
+                        let first = g.text.chars().next().unwrap_or_default();
+                        prompt
+                            .letter_color(&layer(&base, &theme.matched), first)
+                            .apply(g.text)
+                    } else {
+                        base.apply(g.text)
                     }
-                    s
                 })
                 .collect();
 
-            let num = style(format!("{}", y + 1)).blue();
-            let delim = if y == prompt.selection {
-                style("> ").red().bold()
-            } else {
-                style(": ").blue()
-            };
+            if prompt.multi {
+                let marker = if prompt.marked.contains(&to_print.index) {
+                    theme.marker.clone().apply("*")
+                } else {
+                    ContentStyle::new().apply(" ")
+                };
+                queue!(write, Print(marker))?;
+            }
 
             queue!(write, Print(num), Print(delim))?;
 
@@ -126,285 +507,537 @@ where
                 queue!(write, Print(style))?;
             }
         }
+
+        if let Some(preview) = prompt.shown_preview() {
+            let columns = prompt.width - list_width;
+            if columns > 0 {
+                queue!(
+                    write,
+                    MoveToColumn(list_width as u16 + 1),
+                    Clear(ClearType::UntilNewLine),
+                    Print(theme.border.clone().apply("\u{2502} ")),
+                    Print(preview.line(y, columns.saturating_sub(2))),
+                )?;
+            }
+        }
     }
 
-    execute!(
-        write,
-        RestorePosition,
-        MoveRight((prompt.prompt.len() + prompt.text.len()) as u16)
-    )
+    render_preview_rows(prompt, write, PreviewPosition::Bottom)?;
+
+    queue!(write, RestorePosition)?;
+    if top_rows > 0 {
+        queue!(write, MoveDown(top_rows as u16))?;
+    }
+    // a zero move still moves one column
+    let column = width::width(&prompt.prompt)
+        + width::width(&prompt.text[..prompt.byte_offset(prompt.cursor)]);
+    if column > 0 {
+        queue!(write, MoveRight(column as u16))?;
+    }
+    write.flush()?;
+    Ok(())
+}
+
+// Ask for the preview of the highlighted item, if it's not already shown.
+fn update_preview<T>(
+    prompt: &mut Prompt,
+    items: &[RankedItem<T>],
+    previewer: Option<&mut Previewer<T>>,
+) where
+    T: Item,
+{
+    if let (Some(state), Some(previewer)) = (&mut prompt.preview, previewer) {
+        if !state.visible {
+            return;
+        }
+        let item = prompt
+            .selection
+            .checked_sub(prompt.offset)
+            .and_then(|row| items.get(row));
+        if state.select(item.map(|r| r.index)) {
+            if let Some(item) = item {
+                previewer.request(item.index, item.item.clone());
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
-struct RankedItem<T>(Arc<T>, Option<i64>, Vec<usize>)
+struct RankedItem<T>
 where
-    T: Item;
+    T: Item,
+{
+    item: Arc<T>,
+    key: Arc<SearchKey>,
+    score: Option<i64>,
+    indices: Vec<usize>,
+    // position in the original input
+    index: usize,
+}
 
 impl<T> RankedItem<T>
 where
     T: Item,
 {
-    fn rank(&mut self, matcher: &SkimMatcherV2, query: &str) {
-        let result = matcher.fuzzy_indices(&self.0.to_string(), query);
-        if let Some((score, indices)) = result {
-            self.1 = Some(score);
-            self.2 = indices;
-        } else {
-            self.1 = None;
+    fn new(item: Arc<T>, index: usize) -> RankedItem<T> {
+        RankedItem {
+            key: Arc::new(SearchKey::new(&*item)),
+            item,
+            score: None,
+            indices: Vec::new(),
+            index,
         }
     }
 }
 
-impl<T> Ord for RankedItem<T>
+// Original indices of the first `n` results, in display order.
+fn matched<T>(prompt: &Prompt, list: &[RankedItem<T>], search: &mut Search, n: usize) -> Vec<usize>
 where
     T: Item,
 {
-    fn cmp(&self, other: &Self) -> Ordering {
-        let ord = self.1.cmp(&other.1);
-        if ord == Ordering::Equal {
-            return self
-                .0
-                .to_string()
-                .len()
-                .cmp(&other.0.to_string().len())
-                .reverse();
-        }
-        ord
+    if prompt.text.is_empty() {
+        list.iter().take(n).map(|r| r.index).collect()
+    } else {
+        let ranked = search.ranked(n);
+        ranked.iter().map(|m| m.index).collect()
     }
 }
 
-impl<T> Eq for RankedItem<T> where T: Item {}
-
-impl<T> PartialOrd for RankedItem<T>
+// Original index of the highlighted result.
+fn highlighted<T>(prompt: &Prompt, list: &[RankedItem<T>], search: &mut Search) -> Option<usize>
 where
     T: Item,
 {
-    fn partial_cmp(&self, other: &RankedItem<T>) -> Option<Ordering> {
-        self.1.partial_cmp(&other.1)
+    matched(prompt, list, search, prompt.selection + 1)
+        .get(prompt.selection)
+        .copied()
+}
+
+// Highlight the result at `index`, or the last one if there are fewer, scrolling to keep it
+// in view.
+fn select(prompt: &mut Prompt, index: usize) {
+    prompt.selection = index.min(prompt.matches.saturating_sub(1));
+    if prompt.selection < prompt.offset {
+        prompt.offset = prompt.selection;
+    } else if prompt.selection >= prompt.offset + prompt.list_rows() {
+        prompt.offset = prompt.selection + 1 - prompt.list_rows();
+    }
+}
+
+fn select_previous(prompt: &mut Prompt) {
+    if prompt.selection > 0 {
+        select(prompt, prompt.selection - 1);
+    } else {
+        select(prompt, prompt.matches.saturating_sub(1));
+    }
+}
+
+fn select_next(prompt: &mut Prompt) {
+    if prompt.selection + 1 < prompt.matches {
+        select(prompt, prompt.selection + 1);
+    } else {
+        select(prompt, 0);
+    }
+}
+
+// Move the view by a row, dragging the selection along if it would leave the view.
+fn scroll_down(prompt: &mut Prompt) {
+    if prompt.offset + prompt.list_rows() < prompt.matches {
+        prompt.offset += 1;
+        prompt.selection = prompt.selection.max(prompt.offset);
+    }
+}
+
+fn scroll_up(prompt: &mut Prompt) {
+    if prompt.offset > 0 {
+        prompt.offset -= 1;
+        prompt.selection = prompt.selection.min(prompt.offset + prompt.list_rows() - 1);
     }
 }
 
-impl<T> PartialEq for RankedItem<T>
+fn matches<T>(prompt: &Prompt, list: &[RankedItem<T>], search: &Search) -> usize
 where
     T: Item,
 {
-    fn eq(&self, other: &RankedItem<T>) -> bool {
-        self.1 == other.1
+    if prompt.text.is_empty() {
+        list.len()
+    } else {
+        search.len()
     }
 }
 
-fn score_items<T>(matcher: &SkimMatcherV2, items: &mut [RankedItem<T>], query: &str)
+// Count the current results and return the ones in view.
+fn visible<T>(
+    prompt: &mut Prompt,
+    matcher: &dyn Matcher,
+    list: &[RankedItem<T>],
+    search: &mut Search,
+) -> Vec<RankedItem<T>>
 where
     T: Item,
 {
-    items.par_iter_mut().for_each(|item| {
-        item.rank(matcher, query);
-    });
+    prompt.matches = matches(prompt, list, search);
+    prompt.progress = Some(search.progress()).filter(|_| search.busy());
+    select(prompt, prompt.selection);
+
+    if prompt.text.is_empty() {
+        list.iter()
+            .skip(prompt.offset)
+            .take(prompt.list_rows())
+            .cloned()
+            .collect()
+    } else {
+        let (offset, height) = (prompt.offset, prompt.list_rows());
+        search.page(matcher, list, offset, height)
+    }
 }
 
-fn rank_items<T>(scored: &[RankedItem<T>], heap: &mut BinaryHeap<RankedItem<T>>)
+// Indices of the items chosen on accepting: the marked ones in input order, or else the
+// highlighted one.
+fn accepted<T>(prompt: &Prompt, list: &[RankedItem<T>], search: &mut Search) -> Vec<usize>
 where
     T: Item,
 {
-    heap.clear();
-    scored
-        .iter()
-        .filter(|r| r.1.is_some())
-        .for_each(|r| heap.push(r.clone()));
+    if prompt.marked.is_empty() {
+        return highlighted(prompt, list, search).into_iter().collect();
+    }
+
+    prompt.marked.iter().copied().collect()
 }
 
-fn handle_events<W, T>(
-    prompt: &mut Prompt,
-    write: &mut W,
-    list: &mut [RankedItem<T>],
-) -> Result<Option<T>>
+fn outcome<T>(
+    prompt: &Prompt,
+    list: &[RankedItem<T>],
+    indices: Vec<usize>,
+    action: Action,
+    key: Option<KeyEvent>,
+) -> Outcome<T>
 where
-    W: Write,
     T: Item,
 {
-    let matcher = SkimMatcherV2::default();
-    let mut ranked: BinaryHeap<RankedItem<T>> = BinaryHeap::with_capacity(list.len());
+    Outcome {
+        items: indices.iter().map(|&i| (*list[i].item).clone()).collect(),
+        indices,
+        query: prompt.text.clone(),
+        action,
+        key,
+    }
+}
 
-    let mut cache: HashMap<String, Vec<RankedItem<T>>> = HashMap::new();
-    let mut background_cache: Vec<_> = "abcdefghijklmnopqrstuvwxyzABCDEFGIJKLMNOPQRSTUVWXYZ"
-        .chars()
-        .rev()
-        .collect();
+// Shortest time between redraws, while items or results are coming in.
+const FRAME: Duration = Duration::from_millis(16);
 
-    let to_print = list.iter().take(prompt.height).cloned().collect::<Vec<_>>();
-    render(prompt, write, &to_print)?;
-
-    loop {
-        if poll(Duration::from_millis(500))? {
-            let event = read()?;
-            let mut changed = false;
-            let _now = Instant::now();
-
-            match event {
-                Event::Key(KeyEvent { code, .. }) => match code {
-                    KeyCode::Enter => {
-                        let top: Vec<T> = if prompt.text.is_empty() {
-                            list.par_iter()
-                                .take(prompt.selection + 1)
-                                .map(|r| (*r.0).clone())
-                                .collect()
-                        } else {
-                            ranked
-                                .par_iter()
-                                .take(prompt.selection + 1)
-                                .map(|r| (*r.0).clone())
-                                .collect()
-                        };
-
-                        let selected = top.last();
-                        return Ok(selected.cloned());
-                    }
-                    KeyCode::Esc => {
-                        break;
-                    }
-                    KeyCode::Up => {
-                        if prompt.selection > 0 {
-                            prompt.selection -= 1;
-                        } else {
-                            prompt.selection = prompt.height - 1;
-                        }
-                    }
-                    KeyCode::Down => {
-                        if prompt.selection < prompt.height - 1 {
-                            prompt.selection += 1;
-                        } else {
-                            prompt.selection = 0;
-                        }
-                    }
-                    KeyCode::Delete | KeyCode::Backspace => {
-                        prompt.text.pop();
-                        changed = true;
-                    }
-                    KeyCode::Char(c) => {
-                        prompt.text.push(c);
-                        changed = true;
-                    }
-                    _ => {}
-                },
-                Event::Mouse(_) => {}
-                _ => {}
-            }
+// Time between steps of the spinner.
+const SPINNER_STEP: Duration = Duration::from_millis(50);
 
-            if changed && !prompt.text.is_empty() && !cache.contains_key(&prompt.text) {
-                score_items(&matcher, list, &prompt.text);
-                rank_items(&list, &mut ranked);
-            }
+// Longest wait for input when nothing else is going on, and so nothing else can change.
+const IDLE: Duration = Duration::from_secs(60);
 
-        //prompt.prompt = format!("{}ms> ", now.elapsed().as_millis());
-        } else {
-            // background cache
-            if let Some(next) = background_cache.pop() {
-                let mut list_clone = list.to_vec();
-                let mut ranked_clone = BinaryHeap::with_capacity(list_clone.len());
-                score_items(&matcher, &mut list_clone, &next.to_string());
-                rank_items(&list_clone, &mut ranked_clone);
-                cache.insert(
-                    next.to_string(),
-                    ranked_clone
-                        .iter()
-                        .take(prompt.height)
-                        .cloned()
-                        .collect::<Vec<_>>(),
-                );
-            }
-            continue;
-        }
+// Longest gap between the clicks of a double-click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+// Most items taken from a source per tick, so a fast producer can't starve input.
+const RECEIVE_BATCH: usize = 50_000;
 
-        let query = prompt.text.clone();
-        if !query.is_empty() {
-            if let Some(cached) = cache.get(&query) {
-                render(prompt, write, &cached)?;
-                continue;
+// Append newly arrived items to the list and add those matching the current query to the results.
+// Returns how many items were received.
+fn receive<T>(
+    prompt: &mut Prompt,
+    source: &Receiver<T>,
+    list: &mut Vec<RankedItem<T>>,
+    search: &mut Search,
+) -> usize
+where
+    T: Item,
+{
+    let start = list.len();
+    while list.len() - start < RECEIVE_BATCH {
+        match source.try_recv() {
+            Ok(item) => {
+                let index = list.len();
+                list.push(RankedItem::new(Arc::new(item), index));
+            }
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => {
+                prompt.loading = false;
+                break;
             }
         }
-
-        let to_print = if query.is_empty() {
-            list.par_iter()
-                .take(prompt.height)
-                .cloned()
-                .collect::<Vec<_>>()
-        } else {
-            ranked
-                .par_iter()
-                .take(prompt.height)
-                .cloned()
-                .collect::<Vec<_>>()
-        };
-
-        cache.insert(query, to_print.to_vec());
-        render(prompt, write, &to_print)?;
     }
 
-    Ok(None)
+    prompt.loaded = list.len();
+    if list.len() > start {
+        search.extend(list[start..].iter().map(|r| r.key.clone()).collect());
+    }
+    if !prompt.loading {
+        search.end();
+    }
+    list.len() - start
 }
 
-// TODO: turn into builder with options
-pub fn run<T>(items: &[T], height: u16, header: Option<&str>, resize: bool) -> Result<Option<T>>
+fn handle_events<B, E, T>(
+    prompt: &mut Prompt,
+    terminal: &mut Terminal<B, E>,
+    matcher: Arc<dyn Matcher>,
+    list: &mut Vec<RankedItem<T>>,
+    source: Option<Source<T>>,
+    preview: Option<&PreviewFn<'_, T>>,
+    waker: Waker,
+) -> Result<Outcome<T>>
 where
+    B: Backend,
+    E: Events,
     T: Item,
 {
-    enable_raw_mode()?;
-    let mut rng = rand::thread_rng();
+    let Terminal {
+        backend: out,
+        events,
+    } = terminal;
+    // keys are read, and previews made, on threads of their own, which closing waits for
+    thread::scope(|scope| {
+        let mut input = Input::spawn(scope, events, waker.clone());
+        let mut previewer = preview.map(|preview| Previewer::spawn(scope, preview, waker.clone()));
 
-    let final_height = if header.is_none() {
-        height + 1
-    } else {
-        height + 2
-    };
-
-    let (size_cols, size_rows) = size()?;
-    let (_, pos_rows) = position()?;
+        let mut search = Search::new(matcher.clone(), &prompt.tiebreaks, waker.clone());
+        let matcher = &*matcher;
+        // when and on which result the last click was, to spot double-clicks
+        let mut last_click: Option<(Instant, usize)> = None;
 
-    if pos_rows + final_height > size_rows {
-        queue!(stdout(), ScrollUp(final_height), MoveUp(final_height))?;
-    }
+        prompt.loading = source.is_some();
+        prompt.loaded = list.len();
 
-    // Resize terminal and scroll up.
-    queue!(stdout(), EnableMouseCapture, MoveToColumn(1), SavePosition)?;
+        search.extend(list.iter().map(|r| r.key.clone()).collect());
+        if !prompt.loading {
+            search.end();
+        }
+        search.update(&prompt.text);
+        // the first frame waits for the first items and results, if they come quickly
+        let mut next_frame = Instant::now() + FRAME;
+        let mut spun = Instant::now();
+        // whether the screen is out of date
+        let mut stale = true;
 
-    if resize {
-        queue!(stdout(), SetSize(size_cols, final_height))?;
-    }
+        loop {
+            if waker.closed() {
+                return Ok(outcome(prompt, list, Vec::new(), Action::Abort, None));
+            }
+            let received = match &source {
+                Some(source) if prompt.loading => receive(prompt, &source.items, list, &mut search),
+                _ => 0,
+            };
+            let found = search.receive();
+            if received > 0 || found {
+                // keys read before the next render move through the new results
+                prompt.matches = matches(prompt, list, &search);
+                stale = true;
+            }
+            if let (Some(previewer), Some(state)) = (&mut previewer, &mut prompt.preview) {
+                if let Some((index, text)) = previewer.receive() {
+                    state.show(index, &text);
+                    stale = true;
+                }
+            }
 
-    let mut prompt = Prompt {
-        height: height as usize,
-        width: size_cols as usize,
-        header: header.map(|s| s.into()),
-        color_map: "abcdefghijklmnopqrstuvwxyzABCDEFGIJKLMNOPQRSTUVWXYZ"
-            .chars()
-            .map(|c| (c, Color::AnsiValue(rng.gen())))
-            .collect(),
-        ..Prompt::default()
-    };
+            let busy = prompt.loading || search.busy();
+            let now = Instant::now();
+            if busy && now >= spun + SPINNER_STEP {
+                prompt.spinner += 1;
+                spun = now;
+                stale = true;
+            }
+            // draw at most once a frame, and straight away once nothing more is coming
+            if stale && (now >= next_frame || !busy) {
+                let to_print = visible(prompt, matcher, list, &mut search);
+                update_preview(prompt, &to_print, previewer.as_mut());
+                render(prompt, out, &to_print)?;
+                stale = false;
+                next_frame = now + FRAME;
+            }
+            let busy = busy || previewer.as_ref().is_some_and(Previewer::busy);
 
-    let mut list = items
-        .par_iter()
-        .map(|i| RankedItem(Arc::new(i), None, Vec::new()))
-        .collect::<Vec<_>>();
+            // until a key is read, or the worker, the previewer or the source has news, or while
+            // they're coming in, until it's time to draw or the next step of the spinner
+            let deadline = busy.then(|| {
+                let waiting = source.as_ref().filter(|_| prompt.loading);
+                let polled = match waiting.map(|source| &source.waiting) {
+                    Some(Some(waiting)) => {
+                        *waiting.lock().unwrap_or_else(|e| e.into_inner()) = Some(waker.clone());
+                        false
+                    }
+                    Some(None) => true,
+                    None => false,
+                };
+                // sources that can't wake the picker are checked every frame
+                if stale {
+                    next_frame
+                } else if polled {
+                    now + FRAME
+                } else {
+                    spun + SPINNER_STEP
+                }
+            });
+            // the next key is asked for once this one has been dealt with
+            let event = input.receive()?;
+            if event.is_none() {
+                input.request(deadline.map(|deadline| deadline.saturating_duration_since(now)));
+            }
 
-    let result = handle_events(&mut prompt, &mut stdout(), &mut list)?;
+            if let Some(event) = event {
+                let mut changed = false;
+                stale = true;
 
-    // clean up
+                // choosing items waits for all the results they're chosen from
+                let chooses = match event {
+                    Event::Key(key) => matches!(
+                        prompt.keymap.action(key),
+                        Some(
+                            Action::Accept
+                                | Action::Toggle
+                                | Action::ToggleUp
+                                | Action::ToggleDown
+                                | Action::SelectAll
+                                | Action::ToggleAll
+                        )
+                    ),
+                    Event::Mouse(MouseEvent::Down(..)) => true,
+                    _ => false,
+                };
+                if chooses && search.busy() {
+                    search.finish();
+                    prompt.matches = matches(prompt, list, &search);
+                }
 
-    queue!(stdout(), RestorePosition)?;
-    for _ in 0..final_height {
-        queue!(stdout(), MoveToNextLine(1), Clear(ClearType::UntilNewLine))?;
-    }
+                match event {
+                    Event::Key(key) => match prompt.keymap.action(key) {
+                        Some(Action::Accept) => {
+                            let indices = accepted(prompt, list, &mut search);
+                            return Ok(outcome(prompt, list, indices, Action::Accept, Some(key)));
+                        }
+                        Some(Action::Abort) => {
+                            return Ok(outcome(prompt, list, Vec::new(), Action::Abort, Some(key)));
+                        }
+                        Some(Action::Up) => select_previous(prompt),
+                        Some(Action::Down) => select_next(prompt),
+                        Some(Action::PageUp) => {
+                            select(prompt, prompt.selection.saturating_sub(prompt.list_rows()))
+                        }
+                        Some(Action::PageDown) => {
+                            select(prompt, prompt.selection + prompt.list_rows())
+                        }
+                        Some(Action::First) => select(prompt, 0),
+                        Some(Action::Last) => select(prompt, prompt.matches.saturating_sub(1)),
+                        Some(Action::BeginningOfLineOrFirst) if prompt.cursor == 0 => {
+                            select(prompt, 0)
+                        }
+                        Some(Action::BeginningOfLineOrFirst) => {
+                            prompt.edit(Action::BeginningOfLine);
+                        }
+                        Some(Action::EndOfLineOrLast)
+                            if prompt.cursor == prompt.text.chars().count() =>
+                        {
+                            select(prompt, prompt.matches.saturating_sub(1))
+                        }
+                        Some(Action::EndOfLineOrLast) => {
+                            prompt.edit(Action::EndOfLine);
+                        }
+                        Some(action @ (Action::Toggle | Action::ToggleUp | Action::ToggleDown))
+                            if prompt.multi =>
+                        {
+                            if let Some(i) = highlighted(prompt, list, &mut search) {
+                                prompt.toggle_mark(i);
+                            }
+                            match action {
+                                Action::ToggleDown => select_next(prompt),
+                                Action::ToggleUp => select_previous(prompt),
+                                _ => {}
+                            }
+                        }
+                        Some(Action::SelectAll) if prompt.multi => {
+                            for i in matched(prompt, list, &mut search, prompt.matches) {
+                                prompt.mark(i);
+                            }
+                        }
+                        Some(Action::DeselectAll) => {
+                            prompt.marked.clear();
+                        }
+                        Some(Action::ToggleAll) if prompt.multi => {
+                            for i in matched(prompt, list, &mut search, prompt.matches) {
+                                prompt.toggle_mark(i);
+                            }
+                        }
+                        Some(action) if action.edits() => changed = prompt.edit(action),
+                        Some(Action::TogglePreview) => {
+                            if let Some(preview) = &mut prompt.preview {
+                                preview.visible = !preview.visible;
+                            }
+                        }
+                        Some(Action::PreviewUp) => {
+                            if let Some(preview) = &mut prompt.preview {
+                                preview.scroll_up();
+                            }
+                        }
+                        Some(Action::PreviewDown) => {
+                            if let Some(preview) = &mut prompt.preview {
+                                preview.scroll_down();
+                            }
+                        }
+                        Some(_) => {}
+                        None => {
+                            // unbound keys type into the query, unless held with ctrl or alt
+                            if let KeyCode::Char(c) = key.code {
+                                if (key.modifiers - KeyModifiers::SHIFT).is_empty() {
+                                    prompt.insert(&c.to_string());
+                                    changed = true;
+                                }
+                            }
+                        }
+                    },
+                    Event::Mouse(MouseEvent::Down(MouseButton::Left, column, row, _)) => {
+                        if let Some(index) = prompt.result_at(column, row) {
+                            let double = last_click.is_some_and(|(at, clicked)| {
+                                clicked == index && at.elapsed() < DOUBLE_CLICK
+                            });
+                            select(prompt, index);
+                            if double {
+                                let indices = accepted(prompt, list, &mut search);
+                                return Ok(outcome(prompt, list, indices, Action::Accept, None));
+                            }
+                            last_click = Some((Instant::now(), index));
+                        }
+                    }
+                    Event::Mouse(MouseEvent::ScrollDown(..)) => scroll_down(prompt),
+                    Event::Mouse(MouseEvent::ScrollUp(..)) => scroll_up(prompt),
+                    Event::Resize(width, height) => {
+                        prompt.resize(width, height);
+                        // rows the picker no longer reaches would keep what was drawn on them
+                        queue!(out, RestorePosition, Clear(ClearType::FromCursorDown))?;
+                    }
+                    _ => {}
+                }
 
-    execute!(
-        stdout(),
-        SetSize(size_cols, size_rows),
-        DisableMouseCapture,
-        RestorePosition,
-        Clear(ClearType::UntilNewLine)
-    )?;
+                if changed {
+                    prompt.selection = 0;
+                    prompt.offset = 0;
+                    search.update(&prompt.text);
+                    // quick searches are drawn done, rather than drawing the results they replace
+                    next_frame = next_frame.max(Instant::now() + FRAME);
+                }
 
-    disable_raw_mode()?;
+            //prompt.prompt = format!("{}ms> ", now.elapsed().as_millis());
+            } else if !busy || received < RECEIVE_BATCH {
+                waker.wait(deadline.unwrap_or(now + IDLE));
+            }
+        }
+    })
+}
 
-    Ok(result.cloned())
+pub fn run<T>(items: &[T], height: u16, header: Option<&str>, resize: bool) -> Result<Option<T>>
+where
+    T: Item,
+{
+    let mut picker = Picker::new().height(height).resize(resize);
+    if let Some(header) = header {
+        picker = picker.header(header);
+    }
+    picker.run(items)
 }
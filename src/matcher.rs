@@ -0,0 +1,333 @@
+use std::sync::{Arc, RwLock};
+
+use fuzzy_matcher::{clangd::ClangdMatcher, skim::SkimMatcherV2, FuzzyMatcher};
+
+use crate::regex::Regex;
+
+/// How the query is compared against the case of the items.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaseMatching {
+    Respect,
+    Ignore,
+    /// Ignore case unless the query contains an uppercase letter.
+    Smart,
+}
+
+impl CaseMatching {
+    fn ignore(self, query: &str) -> bool {
+        match self {
+            CaseMatching::Respect => false,
+            CaseMatching::Ignore => true,
+            CaseMatching::Smart => !query.chars().any(char::is_uppercase),
+        }
+    }
+}
+
+/// Scores items against a query.
+pub trait Matcher: Send + Sync {
+    /// Score `choice` against `query` along with the char indices of `choice` that matched,
+    /// or `None` if it doesn't match. Higher scores rank first.
+    fn match_indices(&self, choice: &str, query: &str) -> Option<(i64, Vec<usize>)>;
+
+    fn score(&self, choice: &str, query: &str) -> Option<i64> {
+        self.match_indices(choice, query).map(|(score, _)| score)
+    }
+
+    /// Whether every item matching `query` also matches `previous`, which it starts with, so
+    /// `query` only needs to be matched against the results of `previous`. Defaults to `false`,
+    /// which always searches every item.
+    fn narrows(&self, _previous: &str, _query: &str) -> bool {
+        false
+    }
+
+    /// Chars that every item matching `query` contains, ignoring case, so items missing any of
+    /// them are skipped without being matched. Defaults to none.
+    fn required(&self, _query: &str) -> String {
+        String::new()
+    }
+}
+
+/// The built-in matchers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// fzf-style fuzzy matching.
+    SkimV2,
+    /// Fuzzy matching tuned for identifiers, as in clangd's code completion.
+    Clangd,
+    /// The query appears somewhere in the item.
+    Exact,
+    /// The item starts with the query.
+    Prefix,
+    /// The query is a regular expression.
+    Regex,
+}
+
+impl Algorithm {
+    pub fn matcher(self, case: CaseMatching) -> Arc<dyn Matcher> {
+        match self {
+            Algorithm::SkimV2 => Arc::new(SkimV2::new(case)),
+            Algorithm::Clangd => Arc::new(Clangd::new(case)),
+            Algorithm::Exact => Arc::new(Exact::new(case)),
+            Algorithm::Prefix => Arc::new(Prefix::new(case)),
+            Algorithm::Regex => Arc::new(RegexMatcher::new(case)),
+        }
+    }
+}
+
+pub struct SkimV2(SkimMatcherV2);
+
+impl SkimV2 {
+    pub fn new(case: CaseMatching) -> SkimV2 {
+        let matcher = SkimMatcherV2::default();
+        SkimV2(match case {
+            CaseMatching::Respect => matcher.respect_case(),
+            CaseMatching::Ignore => matcher.ignore_case(),
+            CaseMatching::Smart => matcher.smart_case(),
+        })
+    }
+}
+
+impl Matcher for SkimV2 {
+    fn match_indices(&self, choice: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+        self.0.fuzzy_indices(choice, query)
+    }
+
+    fn score(&self, choice: &str, query: &str) -> Option<i64> {
+        self.0.fuzzy_match(choice, query)
+    }
+
+    fn narrows(&self, previous: &str, query: &str) -> bool {
+        query.starts_with(previous)
+    }
+
+    fn required(&self, query: &str) -> String {
+        query.to_string()
+    }
+}
+
+pub struct Clangd(ClangdMatcher);
+
+impl Clangd {
+    pub fn new(case: CaseMatching) -> Clangd {
+        let matcher = ClangdMatcher::default();
+        Clangd(match case {
+            CaseMatching::Respect => matcher.respect_case(),
+            CaseMatching::Ignore => matcher.ignore_case(),
+            CaseMatching::Smart => matcher.smart_case(),
+        })
+    }
+}
+
+impl Matcher for Clangd {
+    fn match_indices(&self, choice: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+        self.0.fuzzy_indices(choice, query)
+    }
+
+    fn score(&self, choice: &str, query: &str) -> Option<i64> {
+        self.0.fuzzy_match(choice, query)
+    }
+
+    fn narrows(&self, previous: &str, query: &str) -> bool {
+        query.starts_with(previous)
+    }
+
+    fn required(&self, query: &str) -> String {
+        query.to_string()
+    }
+}
+
+fn fold(c: char, ignore_case: bool) -> char {
+    if ignore_case {
+        c.to_lowercase().next().unwrap_or(c)
+    } else {
+        c
+    }
+}
+
+// Rank a match of `len` chars at `start`: longer matches first, then matches nearer the start,
+// with a bonus for starting on a word boundary.
+fn span_score(choice: &[char], start: usize, len: usize) -> i64 {
+    let boundary = start == 0 || !choice[start - 1].is_alphanumeric();
+    let bonus = if boundary { 8 } else { 0 };
+    (len as i64) * 16 + bonus - start as i64
+}
+
+fn span(start: usize, len: usize) -> Vec<usize> {
+    (start..start + len).collect()
+}
+
+pub struct Exact(CaseMatching);
+
+impl Exact {
+    pub fn new(case: CaseMatching) -> Exact {
+        Exact(case)
+    }
+}
+
+impl Matcher for Exact {
+    fn match_indices(&self, choice: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+        let ignore_case = self.0.ignore(query);
+        let choice: Vec<_> = choice.chars().collect();
+        let query: Vec<_> = query.chars().map(|c| fold(c, ignore_case)).collect();
+        if query.len() > choice.len() {
+            return None;
+        }
+
+        let start = (0..=choice.len() - query.len()).find(|&start| {
+            choice[start..]
+                .iter()
+                .zip(&query)
+                .all(|(&c, &q)| fold(c, ignore_case) == q)
+        })?;
+        Some((
+            span_score(&choice, start, query.len()),
+            span(start, query.len()),
+        ))
+    }
+
+    fn narrows(&self, previous: &str, query: &str) -> bool {
+        query.starts_with(previous)
+    }
+
+    fn required(&self, query: &str) -> String {
+        query.to_string()
+    }
+}
+
+pub struct Prefix(CaseMatching);
+
+impl Prefix {
+    pub fn new(case: CaseMatching) -> Prefix {
+        Prefix(case)
+    }
+}
+
+impl Matcher for Prefix {
+    fn match_indices(&self, choice: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+        let ignore_case = self.0.ignore(query);
+        let mut choice = choice.chars();
+        let mut len = 0;
+        for q in query.chars() {
+            if fold(choice.next()?, ignore_case) != fold(q, ignore_case) {
+                return None;
+            }
+            len += 1;
+        }
+        Some(((len as i64) * 16, span(0, len)))
+    }
+
+    fn narrows(&self, previous: &str, query: &str) -> bool {
+        query.starts_with(previous)
+    }
+
+    fn required(&self, query: &str) -> String {
+        query.to_string()
+    }
+}
+
+/// Matches items against the query as a regular expression. Invalid patterns match nothing.
+pub struct RegexMatcher {
+    case: CaseMatching,
+    // last compiled query, since every item is matched against the same one
+    compiled: RwLock<Option<(String, Option<Arc<Regex>>)>>,
+}
+
+impl RegexMatcher {
+    pub fn new(case: CaseMatching) -> RegexMatcher {
+        RegexMatcher {
+            case,
+            compiled: RwLock::new(None),
+        }
+    }
+
+    fn regex(&self, query: &str) -> Option<Arc<Regex>> {
+        if let Some((pattern, regex)) = &*self.compiled.read().unwrap() {
+            if pattern == query {
+                return regex.clone();
+            }
+        }
+
+        let regex = Regex::new(query, self.case.ignore(query)).map(Arc::new);
+        *self.compiled.write().unwrap() = Some((query.to_string(), regex.clone()));
+        regex
+    }
+}
+
+impl Matcher for RegexMatcher {
+    fn match_indices(&self, choice: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+        let regex = self.regex(query)?;
+        let choice: Vec<_> = choice.chars().collect();
+        let (start, end) = regex.find(&choice)?;
+        Some((
+            span_score(&choice, start, end - start),
+            span(start, end - start),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(matcher: &dyn Matcher, choice: &str, query: &str) -> Option<Vec<usize>> {
+        matcher
+            .match_indices(choice, query)
+            .map(|(_, indices)| indices)
+    }
+
+    #[test]
+    fn exact_finds_the_query_anywhere() {
+        let exact = Exact::new(CaseMatching::Smart);
+        assert_eq!(
+            indices(&exact, "src/main.rs", "main"),
+            Some(vec![4, 5, 6, 7])
+        );
+        assert_eq!(
+            indices(&exact, "src/Main.rs", "main"),
+            Some(vec![4, 5, 6, 7])
+        );
+        assert_eq!(indices(&exact, "src/main.rs", "Main"), None);
+        assert_eq!(indices(&exact, "mn", "main"), None);
+        assert_eq!(indices(&exact, "Éclair", "éc"), Some(vec![0, 1]));
+        // longer matches first, then earlier ones, with word starts ahead
+        let score = |choice| exact.score(choice, "ab").unwrap();
+        assert!(score("ab") > score("xab"));
+        assert!(score("x ab") > score("xab"));
+    }
+
+    #[test]
+    fn prefix_matches_only_the_start() {
+        let prefix = Prefix::new(CaseMatching::Ignore);
+        assert_eq!(indices(&prefix, "Cargo.toml", "car"), Some(vec![0, 1, 2]));
+        assert_eq!(indices(&prefix, "Cargo.toml", "toml"), None);
+        assert_eq!(indices(&prefix, "ca", "cargo"), None);
+        let respect = Prefix::new(CaseMatching::Respect);
+        assert_eq!(indices(&respect, "Cargo.toml", "car"), None);
+    }
+
+    #[test]
+    fn clangd_matches_identifiers_fuzzily() {
+        let clangd = Clangd::new(CaseMatching::Smart);
+        assert_eq!(indices(&clangd, "match_indices", "mi"), Some(vec![0, 6]));
+        assert!(clangd.score("match_indices", "mi").is_some());
+        assert_eq!(indices(&clangd, "match_indices", "xyz"), None);
+        assert!(clangd.narrows("ma", "mat"));
+        assert!(!clangd.narrows("mat", "ma"));
+    }
+
+    #[test]
+    fn regex_matches_the_query_as_a_pattern() {
+        let regex = RegexMatcher::new(CaseMatching::Smart);
+        assert_eq!(
+            indices(&regex, "v1.22.3", r"\d+\.\d+"),
+            Some(vec![1, 2, 3, 4])
+        );
+        assert_eq!(indices(&regex, "README", "^read"), Some(vec![0, 1, 2, 3]));
+        assert_eq!(indices(&regex, "README", "^Read"), None);
+        // invalid and oversized patterns match nothing rather than failing
+        assert_eq!(indices(&regex, "(a", "(a"), None);
+        assert_eq!(indices(&regex, "haaa", "h(a?){300000}"), None);
+        // results of a longer pattern aren't always results of a shorter one
+        assert!(!regex.narrows("ab", "ab|c"));
+    }
+}
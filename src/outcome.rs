@@ -0,0 +1,40 @@
+use crossterm::event::KeyEvent;
+
+use crate::Action;
+
+/// How the picker was closed, and what was chosen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outcome<T> {
+    /// Chosen items in input order: the marked ones, or else the highlighted one. Empty if the
+    /// picker was aborted, or accepted with no results.
+    pub items: Vec<T>,
+    /// Index of each chosen item in the input.
+    pub indices: Vec<usize>,
+    /// The query when the picker was closed.
+    pub query: String,
+    /// `Action::Accept` or `Action::Abort`.
+    pub action: Action,
+    /// The key that closed the picker, or `None` if it was a double-click, or the picker's
+    /// `Selection` was dropped.
+    pub key: Option<KeyEvent>,
+}
+
+impl<T> Outcome<T> {
+    pub fn is_accepted(&self) -> bool {
+        self.action == Action::Accept
+    }
+
+    /// Whether the picker was closed with `key`, such as one given to `Picker::expect`.
+    pub fn closed_with(&self, key: KeyEvent) -> bool {
+        self.key == Some(key)
+    }
+
+    /// The chosen items if the picker was accepted, as returned by `Picker::select`.
+    pub fn selected(self) -> Option<Vec<T>> {
+        if self.is_accepted() {
+            Some(self.items)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use crossterm::{cursor::*, event::*, execute, queue, style::*, terminal::*, Result};
+use rand::Rng;
+use rayon::prelude::*;
+
+#[cfg(feature = "async")]
+use std::thread;
+
+use crate::fields::{FieldMatcher, FieldOptions};
+#[cfg(feature = "async")]
+use crate::future::{self, Selection};
+use crate::preview::Preview;
+use crate::query::Extended;
+use crate::waker::Waker;
+use crate::{
+    handle_events, Action, Algorithm, Backend, CaseMatching, Delimiter, Events, Fields, Item,
+    KeyEvent, KeyMap, Matcher, Outcome, PreviewFn, PreviewPosition, PreviewSize, PreviewWindow,
+    Prompt, RankedItem, Source, Terminal, Theme, Tiebreak,
+};
+
+const COLOR_LETTERS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGIJKLMNOPQRSTUVWXYZ";
+
+/// Builder for configuring and running the picker.
+///
+/// ```no_run
+/// let result = picky::Picker::new()
+///     .height(10)
+///     .header("animals")
+///     .run(&["dogs", "cats", "mice"])
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct Picker<T> {
+    prompt: String,
+    height: u16,
+    header: Option<String>,
+    resize: bool,
+    query: String,
+    colors: bool,
+    theme: Theme,
+    case: CaseMatching,
+    algorithm: Algorithm,
+    matcher: Option<Arc<dyn Matcher>>,
+    extended: bool,
+    fields: FieldOptions,
+    tiebreaks: Vec<Tiebreak>,
+    max_selections: Option<usize>,
+    preview: Option<Arc<PreviewFn<'static, T>>>,
+    item_preview: bool,
+    preview_window: PreviewWindow,
+    keymap: KeyMap,
+    expect: Vec<KeyEvent>,
+    mouse: bool,
+    _item: PhantomData<T>,
+}
+
+impl<T> Default for Picker<T>
+where
+    T: Item,
+{
+    fn default() -> Picker<T> {
+        Picker {
+            prompt: "> ".to_string(),
+            height: 5,
+            header: None,
+            resize: false,
+            query: "".to_string(),
+            colors: !Theme::no_color(),
+            theme: Theme::default(),
+            case: CaseMatching::Smart,
+            algorithm: Algorithm::SkimV2,
+            matcher: None,
+            extended: true,
+            fields: FieldOptions::default(),
+            tiebreaks: vec![Tiebreak::Length],
+            max_selections: None,
+            preview: None,
+            item_preview: false,
+            preview_window: PreviewWindow::default(),
+            keymap: KeyMap::default(),
+            expect: Vec::new(),
+            mouse: true,
+            _item: PhantomData,
+        }
+    }
+}
+
+impl<T> Picker<T>
+where
+    T: Item,
+{
+    pub fn new() -> Picker<T> {
+        Picker::default()
+    }
+
+    /// Text drawn in front of the query.
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Picker<T> {
+        self.prompt = prompt.into();
+        self
+    }
+
+    /// Number of result rows to display.
+    pub fn height(mut self, height: u16) -> Picker<T> {
+        self.height = height;
+        self
+    }
+
+    /// Line shown between the prompt and the results.
+    pub fn header(mut self, header: impl Into<String>) -> Picker<T> {
+        self.header = Some(header.into());
+        self
+    }
+
+    /// Shrink the terminal to fit the picker while it is open.
+    pub fn resize(mut self, resize: bool) -> Picker<T> {
+        self.resize = resize;
+        self
+    }
+
+    /// Query to start with.
+    pub fn query(mut self, query: impl Into<String>) -> Picker<T> {
+        self.query = query.into();
+        self
+    }
+
+    /// Draw in color, on unless the `NO_COLOR` environment variable is set.
+    ///
+    /// Without colors the picker uses `Theme::monochrome`, whatever the theme.
+    pub fn colors(mut self, colors: bool) -> Picker<T> {
+        self.colors = colors;
+        self
+    }
+
+    /// Colors and attributes for each part of the picker.
+    pub fn theme(mut self, theme: Theme) -> Picker<T> {
+        self.theme = theme;
+        self
+    }
+
+    pub fn case(mut self, case: CaseMatching) -> Picker<T> {
+        self.case = case;
+        self
+    }
+
+    /// Which built-in matcher to use, fuzzy skim-v2 by default.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Picker<T> {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Use a custom matcher instead of one of the built-in algorithms.
+    pub fn matcher(mut self, matcher: impl Matcher + 'static) -> Picker<T> {
+        self.matcher = Some(Arc::new(matcher));
+        self
+    }
+
+    /// Parse the query with fzf's extended search syntax, on by default.
+    ///
+    /// Space separated terms must all match, and terms joined by ` | ` match if any of them do.
+    /// Terms are fuzzy unless written as `'exact`, `^prefix`, `suffix$` or `^equal$`, and
+    /// `!term` excludes items containing `term`. The regex algorithm always sees the whole query.
+    pub fn extended(mut self, extended: bool) -> Picker<T> {
+        self.extended = extended;
+        self
+    }
+
+    /// How items are split into fields for `nth` and `with_nth`. Runs of whitespace by default.
+    pub fn delimiter(mut self, delimiter: Delimiter) -> Picker<T> {
+        self.fields.delimiter = delimiter;
+        self
+    }
+
+    /// Only match the query against these fields of each item, such as the name column of a
+    /// table. Indices count the fields shown when `with_nth` is set.
+    ///
+    /// ```no_run
+    /// let picker = picky::Picker::<&str>::new().nth("1,-1".parse().unwrap());
+    /// ```
+    pub fn nth(mut self, fields: Fields) -> Picker<T> {
+        self.fields.nth = Some(fields);
+        self
+    }
+
+    /// Only show these fields of each item. The whole item is still returned when chosen.
+    pub fn with_nth(mut self, fields: Fields) -> Picker<T> {
+        self.fields.with_nth = Some(fields);
+        self
+    }
+
+    /// Order results with the same score by these in turn, then by input order. Shorter items
+    /// come first by default.
+    ///
+    /// ```no_run
+    /// use picky::{Picker, Tiebreak};
+    ///
+    /// let picker = Picker::<&str>::new().tiebreak(vec![Tiebreak::Begin, Tiebreak::Length]);
+    /// ```
+    pub fn tiebreak(mut self, tiebreaks: Vec<Tiebreak>) -> Picker<T> {
+        self.tiebreaks = tiebreaks;
+        self
+    }
+
+    /// Limit how many items can be marked by `run_multi`.
+    pub fn max_selections(mut self, max: usize) -> Picker<T> {
+        self.max_selections = Some(max);
+        self
+    }
+
+    /// Show the output of `preview` for the highlighted item next to the results.
+    ///
+    /// `preview` runs on a thread of its own, so a slow one doesn't hold up typing, though
+    /// closing the picker waits for it. Shift-Up and Shift-Down scroll the preview and Alt-P
+    /// hides or shows it.
+    pub fn preview<F>(mut self, preview: F) -> Picker<T>
+    where
+        F: Fn(&T) -> String + Send + Sync + 'static,
+    {
+        self.preview = Some(Arc::new(preview));
+        self
+    }
+
+    /// Show `Item::preview` of the highlighted item next to the results, as with `preview`.
+    pub fn item_preview(mut self) -> Picker<T> {
+        self.item_preview = true;
+        self
+    }
+
+    /// Where to draw the preview and how big it is, half the width on the right by default.
+    pub fn preview_window(mut self, position: PreviewPosition, size: PreviewSize) -> Picker<T> {
+        self.preview_window = PreviewWindow { position, size };
+        self
+    }
+
+    /// Run `action` when `key` is pressed, replacing any existing binding.
+    ///
+    /// ```no_run
+    /// use picky::{Action, KeyCode, KeyEvent, KeyModifiers, Picker};
+    ///
+    /// let picker = Picker::<&str>::new()
+    ///     .bind(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::CONTROL), Action::Down)
+    ///     .bind(KeyEvent::new(KeyCode::Char('k'), KeyModifiers::CONTROL), Action::Up);
+    /// ```
+    pub fn bind(mut self, key: KeyEvent, action: Action) -> Picker<T> {
+        self.keymap.bind(key, action);
+        self
+    }
+
+    /// Replace all of the key bindings.
+    pub fn keymap(mut self, keymap: KeyMap) -> Picker<T> {
+        self.keymap = keymap;
+        self
+    }
+
+    /// Accept the picker when `key` is pressed, whatever it is bound to.
+    ///
+    /// `Outcome::key` tells which key closed the picker, so different keys can do different
+    /// things with the selection, like fzf's `--expect`.
+    pub fn expect(mut self, key: KeyEvent) -> Picker<T> {
+        self.expect.push(key);
+        self
+    }
+
+    /// Capture the mouse, so results can be clicked, double-clicked to accept and scrolled with
+    /// the wheel. On by default; turn it off to keep the terminal's own text selection.
+    pub fn mouse(mut self, mouse: bool) -> Picker<T> {
+        self.mouse = mouse;
+        self
+    }
+
+    /// Show the picker and block until an item is chosen or the picker is closed.
+    pub fn run(&self, items: &[T]) -> Result<Option<T>> {
+        Ok(self
+            .pick(items, false)?
+            .and_then(|mut selected| selected.pop()))
+    }
+
+    /// Like `run`, but items can be marked with Tab and Shift-Tab.
+    ///
+    /// Returns every marked item in input order, or the highlighted item if none were marked.
+    /// Alt-A, Alt-D and Alt-T mark all, unmark all and toggle all of the current results.
+    pub fn run_multi(&self, items: &[T]) -> Result<Vec<T>> {
+        Ok(self.pick(items, true)?.unwrap_or_default())
+    }
+
+    /// Like `run`, but items are read from `source` while the picker is open.
+    pub fn run_source(&self, source: impl Into<Source<T>>) -> Result<Option<T>> {
+        Ok(self
+            .select(source, false)?
+            .and_then(|mut selected| selected.pop()))
+    }
+
+    /// Like `run_multi`, but items are read from `source` while the picker is open.
+    pub fn run_multi_source(&self, source: impl Into<Source<T>>) -> Result<Vec<T>> {
+        Ok(self.select(source, true)?.unwrap_or_default())
+    }
+
+    /// Show the picker over `source`, in multi-select mode if `multi` is set.
+    ///
+    /// Returns `None` if the picker was aborted with Esc or Ctrl-C rather than accepted.
+    pub fn select(&self, source: impl Into<Source<T>>, multi: bool) -> Result<Option<Vec<T>>> {
+        self.select_on(&mut Terminal::stdout(), source, multi)
+    }
+
+    /// Like `select`, but drawing on and reading events from `terminal`.
+    pub fn select_on<B, E>(
+        &self,
+        terminal: &mut Terminal<B, E>,
+        source: impl Into<Source<T>>,
+        multi: bool,
+    ) -> Result<Option<Vec<T>>>
+    where
+        B: Backend,
+        E: Events,
+    {
+        Ok(self.outcome_on(terminal, source, multi)?.selected())
+    }
+
+    /// Like `select`, but telling how the picker was closed as well as what was chosen.
+    ///
+    /// ```no_run
+    /// use picky::{KeyCode, KeyEvent, KeyModifiers, Picker};
+    ///
+    /// let edit = KeyEvent::new(KeyCode::Char('e'), KeyModifiers::CONTROL);
+    /// let outcome = Picker::new()
+    ///     .expect(edit)
+    ///     .outcome(vec!["Cargo.toml", "README.md"], false)
+    ///     .unwrap();
+    /// match outcome.items.first() {
+    ///     Some(file) if outcome.closed_with(edit) => println!("edit {}", file),
+    ///     Some(file) => println!("open {}", file),
+    ///     None => println!("nothing picked for {:?}", outcome.query),
+    /// }
+    /// ```
+    pub fn outcome(&self, source: impl Into<Source<T>>, multi: bool) -> Result<Outcome<T>> {
+        self.outcome_on(&mut Terminal::stdout(), source, multi)
+    }
+
+    /// Like `outcome`, but drawing on and reading events from `terminal`.
+    pub fn outcome_on<B, E>(
+        &self,
+        terminal: &mut Terminal<B, E>,
+        source: impl Into<Source<T>>,
+        multi: bool,
+    ) -> Result<Outcome<T>>
+    where
+        B: Backend,
+        E: Events,
+    {
+        let source = Some(source.into());
+        self.session(terminal, Vec::new(), source, multi, Waker::default())
+    }
+
+    /// Like `select`, but run on a thread of its own, returning a future of the result.
+    ///
+    /// Dropping the future closes the picker, as if it was aborted. The thread reads keys with
+    /// blocking calls, not crossterm's `EventStream`, and there's no way to pass an async
+    /// `Stream` of items, as both would need the `futures` crate. Forward a stream's items
+    /// through `Source::channel` instead.
+    #[cfg(feature = "async")]
+    pub fn select_async(&self, source: impl Into<Source<T>>, multi: bool) -> Selection<T>
+    where
+        T: 'static,
+    {
+        let picker = self.clone();
+        let source = Some(source.into());
+        let waker = Waker::default();
+        let (selection, completion) = future::selection(waker.clone());
+        thread::spawn(move || {
+            let outcome = picker.session(&mut Terminal::stdout(), Vec::new(), source, multi, waker);
+            completion.complete(outcome.map(Outcome::selected))
+        });
+        selection
+    }
+
+    fn pick(&self, items: &[T], multi: bool) -> Result<Option<Vec<T>>> {
+        let list = items
+            .par_iter()
+            .enumerate()
+            .map(|(index, i)| RankedItem::new(Arc::new(i.clone()), index))
+            .collect::<Vec<_>>();
+
+        let terminal = &mut Terminal::stdout();
+        Ok(self
+            .session(terminal, list, None, multi, Waker::default())?
+            .selected())
+    }
+
+    // Run the picker on `terminal` until it's closed, by a key or by closing `waker`.
+    pub(crate) fn session<B, E>(
+        &self,
+        terminal: &mut Terminal<B, E>,
+        mut list: Vec<RankedItem<T>>,
+        source: Option<Source<T>>,
+        multi: bool,
+        waker: Waker,
+    ) -> Result<Outcome<T>>
+    where
+        B: Backend,
+        E: Events,
+    {
+        terminal.backend.enable_raw_mode()?;
+        let mut restore = Restore {
+            terminal,
+            screen: None,
+            raw: true,
+        };
+        let out = &mut restore.terminal.backend;
+        let mut rng = rand::thread_rng();
+
+        let item_preview = |item: &T| item.preview().unwrap_or_default();
+        let preview: Option<&PreviewFn<'_, T>> = match &self.preview {
+            Some(preview) => Some(&**preview),
+            None if self.item_preview => Some(&item_preview),
+            None => None,
+        };
+        let preview_rows = match preview {
+            Some(_) => self.preview_window.rows(self.height as usize) as u16,
+            None => 0,
+        };
+        let final_height = if self.header.is_none() {
+            self.height + 1
+        } else {
+            self.height + 2
+        } + preview_rows;
+
+        let (size_cols, size_rows) = out.size()?;
+        let (_, pos_rows) = out.position()?;
+        restore.screen = Some(Screen {
+            rows: final_height,
+            size: (size_cols, size_rows),
+            mouse: self.mouse,
+        });
+
+        if pos_rows + final_height > size_rows {
+            queue!(out, ScrollUp(final_height), MoveUp(final_height))?;
+        }
+
+        // Resize terminal and scroll up.
+        if self.mouse {
+            queue!(out, EnableMouseCapture)?;
+        }
+        execute!(out, MoveToColumn(1), SavePosition)?;
+        let (_, row) = out.position()?;
+
+        if self.resize {
+            queue!(out, SetSize(size_cols, final_height))?;
+        }
+
+        let theme = if self.colors {
+            self.theme.clone()
+        } else {
+            Theme::monochrome()
+        };
+        let color_map = if theme.letter_colors {
+            COLOR_LETTERS
+                .chars()
+                .map(|c| (c, Color::AnsiValue(rng.gen())))
+                .collect()
+        } else {
+            Default::default()
+        };
+
+        let mut keymap = self.keymap.clone();
+        for &key in &self.expect {
+            keymap.bind(key, Action::Accept);
+        }
+
+        let mut prompt = Prompt {
+            prompt: self.prompt.clone(),
+            text: self.query.clone(),
+            cursor: self.query.chars().count(),
+            rows: self.height as usize,
+            height: self.height as usize,
+            width: size_cols as usize,
+            header: self.header.clone(),
+            row: row as usize,
+            color_map,
+            theme,
+            multi,
+            max_selections: self.max_selections,
+            preview: preview.map(|_| Preview::new(self.preview_window)),
+            keymap,
+            fields: self.fields.clone(),
+            tiebreaks: self.tiebreaks.clone(),
+            ..Prompt::default()
+        };
+
+        let matcher = match &self.matcher {
+            Some(matcher) => matcher.clone(),
+            None => self.algorithm.matcher(self.case),
+        };
+        let matcher: Arc<dyn Matcher> = if self.extended && self.algorithm != Algorithm::Regex {
+            let exact = self.matcher.is_none() && self.algorithm == Algorithm::Exact;
+            let subsequence = self.matcher.is_none()
+                && matches!(
+                    self.algorithm,
+                    Algorithm::SkimV2 | Algorithm::Clangd | Algorithm::Exact
+                );
+            Arc::new(Extended::new(matcher, self.case, exact, subsequence))
+        } else {
+            matcher
+        };
+        let matcher: Arc<dyn Matcher> = if self.fields.searches_fields() {
+            Arc::new(FieldMatcher::new(matcher, self.fields.clone()))
+        } else {
+            matcher
+        };
+
+        let result = handle_events(
+            &mut prompt,
+            restore.terminal,
+            matcher,
+            &mut list,
+            source,
+            preview,
+            waker,
+        )?;
+        restore.restore()?;
+        Ok(result)
+    }
+}
+
+// Puts the terminal back as the picker found it once dropped, even if the picker failed or
+// panicked.
+struct Restore<'a, B, E>
+where
+    B: Backend,
+{
+    terminal: &'a mut Terminal<B, E>,
+    // what to clear and undo, once the picker has started drawing
+    screen: Option<Screen>,
+    raw: bool,
+}
+
+struct Screen {
+    // rows drawn on, from the saved cursor position
+    rows: u16,
+    // columns and rows of the terminal before it was resized
+    size: (u16, u16),
+    mouse: bool,
+}
+
+impl<B, E> Restore<'_, B, E>
+where
+    B: Backend,
+{
+    fn restore(&mut self) -> Result<()> {
+        let out = &mut self.terminal.backend;
+        let cleared = match self.screen.take() {
+            Some(screen) => clear(out, &screen),
+            None => Ok(()),
+        };
+        // out of raw mode even if clearing failed
+        self.raw = false;
+        cleared.and(out.disable_raw_mode())
+    }
+}
+
+impl<B, E> Drop for Restore<'_, B, E>
+where
+    B: Backend,
+{
+    fn drop(&mut self) {
+        if self.raw {
+            let _ = self.restore();
+        }
+    }
+}
+
+fn clear(out: &mut impl Backend, screen: &Screen) -> Result<()> {
+    queue!(out, RestorePosition)?;
+    for _ in 0..screen.rows {
+        queue!(out, MoveToNextLine(1), Clear(ClearType::UntilNewLine))?;
+    }
+
+    let (columns, rows) = screen.size;
+    execute!(
+        out,
+        SetSize(columns, rows),
+        RestorePosition,
+        Clear(ClearType::UntilNewLine)
+    )?;
+
+    if screen.mouse {
+        execute!(out, DisableMouseCapture)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,225 @@
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread::Scope,
+};
+
+use crate::waker::Waker;
+use crate::width::truncate;
+use crate::PreviewFn;
+
+/// Where the preview is drawn relative to the results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreviewPosition {
+    Right,
+    Bottom,
+    Top,
+}
+
+/// Size of the preview: columns when it is on the right, rows otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreviewSize {
+    /// Percentage of the terminal width, or of the result rows.
+    Percent(u16),
+    Fixed(u16),
+}
+
+impl PreviewSize {
+    fn of(self, total: usize) -> usize {
+        match self {
+            PreviewSize::Percent(percent) => total * percent.min(100) as usize / 100,
+            PreviewSize::Fixed(size) => (size as usize).min(total),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PreviewWindow {
+    pub position: PreviewPosition,
+    pub size: PreviewSize,
+}
+
+impl Default for PreviewWindow {
+    fn default() -> PreviewWindow {
+        PreviewWindow {
+            position: PreviewPosition::Right,
+            size: PreviewSize::Percent(50),
+        }
+    }
+}
+
+impl PreviewWindow {
+    /// Columns taken from the results by a preview on the right.
+    pub(crate) fn columns(&self, width: usize) -> usize {
+        match self.position {
+            // leave room for the results themselves
+            PreviewPosition::Right => self.size.of(width).min(width.saturating_sub(8)),
+            _ => 0,
+        }
+    }
+
+    /// Rows added above or below the results, including the separator.
+    pub(crate) fn rows(&self, height: usize) -> usize {
+        match self.position {
+            PreviewPosition::Right => 0,
+            _ => self.size.of(height).max(1) + 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Preview {
+    pub(crate) window: PreviewWindow,
+    pub(crate) visible: bool,
+    pub(crate) scroll: usize,
+    // original index of the item the lines are for
+    pub(crate) index: Option<usize>,
+    pub(crate) lines: Vec<String>,
+}
+
+impl Preview {
+    pub(crate) fn new(window: PreviewWindow) -> Preview {
+        Preview {
+            window,
+            visible: true,
+            scroll: 0,
+            index: None,
+            lines: Vec::new(),
+        }
+    }
+
+    /// Switch to the preview of the item at `index`, returning whether it has to be made. The
+    /// last preview stays until then.
+    pub(crate) fn select(&mut self, index: Option<usize>) -> bool {
+        if self.index == index {
+            return false;
+        }
+        self.index = index;
+        self.scroll = 0;
+        if index.is_none() {
+            self.lines.clear();
+        }
+        index.is_some()
+    }
+
+    /// Show `text` as the preview of the item at `index`, if that's still the one selected.
+    pub(crate) fn show(&mut self, index: usize, text: &str) {
+        if self.index != Some(index) {
+            return;
+        }
+        self.scroll = 0;
+        // tabs and control characters would throw off the layout
+        self.lines = text
+            .lines()
+            .map(|line| {
+                line.replace('\t', "    ")
+                    .chars()
+                    .filter(|c| !c.is_control())
+                    .collect()
+            })
+            .collect();
+    }
+
+    pub(crate) fn scroll_down(&mut self) {
+        if self.scroll + 1 < self.lines.len() {
+            self.scroll += 1;
+        }
+    }
+
+    pub(crate) fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+
+    /// The `n`th visible line, truncated to `width` columns.
+    pub(crate) fn line(&self, n: usize, width: usize) -> &str {
+        match self.lines.get(self.scroll + n) {
+            Some(line) if self.visible => truncate(line, width),
+            _ => "",
+        }
+    }
+}
+
+/// Makes previews on a thread of its own, so a slow one never holds up drawing or typing.
+///
+/// Only the newest of the previews asked for while one is being made is made next.
+pub(crate) struct Previewer<T> {
+    requests: Sender<(usize, Arc<T>)>,
+    previews: Receiver<(usize, String)>,
+    // original index of the item whose preview was asked for last, until it's made
+    pending: Option<usize>,
+}
+
+impl<T> Previewer<T>
+where
+    T: Send + Sync,
+{
+    /// Make previews with `preview` on a thread of `scope`, waking `waker` as each is made.
+    pub(crate) fn spawn<'scope, 'env>(
+        scope: &'scope Scope<'scope, 'env>,
+        preview: &'env PreviewFn<'env, T>,
+        waker: Waker,
+    ) -> Previewer<T>
+    where
+        T: 'env,
+    {
+        let (requests, requested) = channel::<(usize, Arc<T>)>();
+        let (made, previews) = channel();
+        scope.spawn(move || {
+            while let Ok(mut request) = requested.recv() {
+                request = requested.try_iter().last().unwrap_or(request);
+                let (index, item) = request;
+                // a preview that panics is shown as an error, rather than never coming
+                let text = panic::catch_unwind(AssertUnwindSafe(|| preview(&item)))
+                    .unwrap_or_else(|panic| format!("preview failed: {}", message(&*panic)));
+                if made.send((index, text)).is_err() {
+                    break;
+                }
+                waker.wake();
+            }
+        });
+        Previewer {
+            requests,
+            previews,
+            pending: None,
+        }
+    }
+
+    pub(crate) fn request(&mut self, index: usize, item: Arc<T>) {
+        // the thread only stops once this is dropped
+        let _ = self.requests.send((index, item));
+        self.pending = Some(index);
+    }
+
+    /// Whether the preview asked for last is still being made.
+    pub(crate) fn busy(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// The preview asked for last, once it's made.
+    pub(crate) fn receive(&mut self) -> Option<(usize, String)> {
+        let pending = self.pending?;
+        let made = self
+            .previews
+            .try_iter()
+            .filter(|(i, _)| *i == pending)
+            .last();
+        if made.is_some() {
+            self.pending = None;
+        }
+        made
+    }
+}
+
+// What a panic was called with, if it was a message.
+fn message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic
+            .downcast_ref::<String>()
+            .map_or("panicked", String::as_str),
+    }
+}
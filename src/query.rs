@@ -0,0 +1,363 @@
+use std::sync::{Arc, RwLock};
+
+use crate::matcher::{Exact, Prefix, SkimV2};
+use crate::{CaseMatching, Matcher};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Fuzzy,
+    Exact,
+    Prefix,
+    Suffix,
+    Equal,
+}
+
+#[derive(Clone, Debug)]
+struct Term {
+    kind: Kind,
+    text: String,
+    negated: bool,
+}
+
+impl Term {
+    // `exact` swaps the meaning of plain and `'` quoted terms, as with fzf's --exact.
+    fn parse(mut token: &str, exact: bool) -> Term {
+        let negated = token.starts_with('!');
+        if negated {
+            token = &token[1..];
+        }
+
+        let mut kind = if exact || negated {
+            Kind::Exact
+        } else {
+            Kind::Fuzzy
+        };
+
+        if token.starts_with('\'') {
+            token = &token[1..];
+            kind = if kind == Kind::Exact && !negated {
+                Kind::Fuzzy
+            } else {
+                Kind::Exact
+            };
+        } else {
+            let prefix = token.starts_with('^');
+            if prefix {
+                token = &token[1..];
+            }
+            let suffix = token.ends_with('$') && !token.ends_with("\\$");
+            if suffix {
+                token = &token[..token.len() - 1];
+            }
+            kind = match (prefix, suffix) {
+                (true, true) => Kind::Equal,
+                (true, false) => Kind::Prefix,
+                (false, true) => Kind::Suffix,
+                (false, false) => kind,
+            };
+        }
+
+        Term {
+            kind,
+            text: token.replace("\\$", "$"),
+            negated,
+        }
+    }
+
+    fn match_indices(
+        &self,
+        matcher: &dyn Matcher,
+        case: CaseMatching,
+        choice: &str,
+    ) -> Option<(i64, Vec<usize>)> {
+        match self.kind {
+            Kind::Fuzzy => matcher.match_indices(choice, &self.text),
+            Kind::Exact => Exact::new(case).match_indices(choice, &self.text),
+            Kind::Prefix => Prefix::new(case).match_indices(choice, &self.text),
+            Kind::Suffix => suffix(case, choice, &self.text),
+            Kind::Equal => {
+                let len = choice.chars().count();
+                if self.text.chars().count() != len {
+                    return None;
+                }
+                Prefix::new(case).match_indices(choice, &self.text)
+            }
+        }
+    }
+
+    // Chars an item must contain to match, when not negated.
+    fn required(&self, matcher: &dyn Matcher) -> String {
+        match self.kind {
+            Kind::Fuzzy => matcher.required(&self.text),
+            _ => self.text.clone(),
+        }
+    }
+}
+
+fn suffix(case: CaseMatching, choice: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let len = choice.chars().count();
+    let text_len = text.chars().count();
+    let start = len.checked_sub(text_len)?;
+    let tail: String = choice.chars().skip(start).collect();
+    let (score, indices) = Prefix::new(case).match_indices(&tail, text)?;
+    Some((score, indices.into_iter().map(|i| i + start).collect()))
+}
+
+// Split on unescaped whitespace, unescaping `\ `.
+fn tokens(query: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&' ') => {
+                token.push(' ');
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// A query in fzf's extended search syntax.
+///
+/// Space separated terms must all match, and terms joined by ` | ` match if any of them do.
+/// Each term is fuzzy by default, `'exact` for a substring, `^prefix`, `suffix$`, `^equal$`,
+/// and `!term` for items that don't contain `term`.
+#[derive(Clone, Debug)]
+struct Query {
+    // every group must match, and a group matches if any of its terms do
+    groups: Vec<Vec<Term>>,
+}
+
+impl Query {
+    fn parse(query: &str, exact: bool) -> Query {
+        let mut groups: Vec<Vec<Term>> = Vec::new();
+        let mut or = false;
+        for token in tokens(query) {
+            if token == "|" {
+                or = !groups.is_empty();
+                continue;
+            }
+
+            let term = Term::parse(&token, exact);
+            if term.text.is_empty() {
+                // a lone `'`, `^`, `$` or `!` doesn't narrow anything
+                or = false;
+                continue;
+            }
+
+            match groups.last_mut() {
+                Some(group) if or => group.push(term),
+                _ => groups.push(vec![term]),
+            }
+            or = false;
+        }
+        Query { groups }
+    }
+
+    // Chars of the groups with a single term, which every match has to contain.
+    fn required(&self, matcher: &dyn Matcher) -> String {
+        self.groups
+            .iter()
+            .filter_map(|group| match group.as_slice() {
+                [term] if !term.negated => Some(term.required(matcher)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn match_indices(
+        &self,
+        matcher: &dyn Matcher,
+        case: CaseMatching,
+        choice: &str,
+    ) -> Option<(i64, Vec<usize>)> {
+        let mut score = 0;
+        let mut indices = Vec::new();
+
+        for group in &self.groups {
+            let (group_score, group_indices) = group
+                .iter()
+                .filter_map(|term| {
+                    let result = term.match_indices(matcher, case, choice);
+                    if term.negated {
+                        match result {
+                            Some(_) => None,
+                            None => Some((0, Vec::new())),
+                        }
+                    } else {
+                        result
+                    }
+                })
+                .max_by_key(|(score, _)| *score)?;
+            score += group_score;
+            indices.extend(group_indices);
+        }
+
+        indices.sort_unstable();
+        indices.dedup();
+        Some((score, indices))
+    }
+}
+
+/// Wraps a matcher to understand the extended search syntax, passing fuzzy terms through to it.
+pub(crate) struct Extended {
+    matcher: Arc<dyn Matcher>,
+    case: CaseMatching,
+    exact: bool,
+    // whether the matcher matches items containing its query in order, as anchored terms do
+    subsequence: bool,
+    // last parsed query, since every item is matched against the same one
+    parsed: RwLock<Option<(String, Arc<Query>)>>,
+}
+
+impl Extended {
+    /// With `exact`, plain terms are exact and `'` quoted terms are fuzzy. `subsequence` says
+    /// the matcher matches every item containing the query's chars in order.
+    pub(crate) fn new(
+        matcher: Arc<dyn Matcher>,
+        case: CaseMatching,
+        exact: bool,
+        subsequence: bool,
+    ) -> Extended {
+        let matcher = if exact {
+            // quoted terms still need a fuzzy matcher
+            Arc::new(SkimV2::new(case))
+        } else {
+            matcher
+        };
+        Extended {
+            matcher,
+            case,
+            exact,
+            subsequence,
+            parsed: RwLock::new(None),
+        }
+    }
+
+    fn query(&self, query: &str) -> Arc<Query> {
+        if let Some((text, parsed)) = &*self.parsed.read().unwrap() {
+            if text == query {
+                return parsed.clone();
+            }
+        }
+
+        let parsed = Arc::new(Query::parse(query, self.exact));
+        *self.parsed.write().unwrap() = Some((query.to_string(), parsed.clone()));
+        parsed
+    }
+}
+
+impl Matcher for Extended {
+    fn match_indices(&self, choice: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+        self.query(query)
+            .match_indices(&*self.matcher, self.case, choice)
+    }
+
+    // Typing more only narrows while it can't start an OR or a negation, or turn the end of
+    // the last term into an escape. Anchoring the end of a term only narrows it when the
+    // matcher takes any item with the term's chars in order, which a suffix match has.
+    fn narrows(&self, previous: &str, query: &str) -> bool {
+        query.starts_with(previous)
+            && !query.contains(['|', '!'])
+            && !previous.ends_with(['$', '\\'])
+            && (self.subsequence || !query[previous.len()..].contains('$'))
+            && self.matcher.narrows(previous, query)
+    }
+
+    fn required(&self, query: &str) -> String {
+        self.query(query).required(&*self.matcher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(token: &str, exact: bool) -> (Kind, String, bool) {
+        let term = Term::parse(token, exact);
+        (term.kind, term.text, term.negated)
+    }
+
+    fn groups(query: &str) -> Vec<Vec<String>> {
+        Query::parse(query, false)
+            .groups
+            .into_iter()
+            .map(|group| group.into_iter().map(|term| term.text).collect())
+            .collect()
+    }
+
+    fn extended(subsequence: bool) -> Extended {
+        let matcher = Arc::new(SkimV2::new(CaseMatching::Smart));
+        Extended::new(matcher, CaseMatching::Smart, false, subsequence)
+    }
+
+    #[test]
+    fn splits_tokens_on_unescaped_whitespace() {
+        assert_eq!(tokens("  ab\tc  d "), ["ab", "c", "d"]);
+        assert_eq!(tokens(r"a\ b c\ "), ["a b", "c "]);
+        assert_eq!(tokens(r"a\b"), [r"a\b"]);
+        assert!(tokens("   ").is_empty());
+    }
+
+    #[test]
+    fn parses_each_kind_of_term() {
+        let text = |s: &str| s.to_string();
+        assert_eq!(term("ab", false), (Kind::Fuzzy, text("ab"), false));
+        assert_eq!(term("'ab", false), (Kind::Exact, text("ab"), false));
+        assert_eq!(term("^ab", false), (Kind::Prefix, text("ab"), false));
+        assert_eq!(term("ab$", false), (Kind::Suffix, text("ab"), false));
+        assert_eq!(term("^ab$", false), (Kind::Equal, text("ab"), false));
+        assert_eq!(term(r"ab\$", false), (Kind::Fuzzy, text("ab$"), false));
+        assert_eq!(term("!ab", false), (Kind::Exact, text("ab"), true));
+        assert_eq!(term("!'ab", false), (Kind::Exact, text("ab"), true));
+        assert_eq!(term("!^ab", false), (Kind::Prefix, text("ab"), true));
+    }
+
+    #[test]
+    fn exact_mode_swaps_fuzzy_and_exact_terms() {
+        let text = |s: &str| s.to_string();
+        assert_eq!(term("ab", true), (Kind::Exact, text("ab"), false));
+        assert_eq!(term("'ab", true), (Kind::Fuzzy, text("ab"), false));
+        assert_eq!(term("!ab", true), (Kind::Exact, text("ab"), true));
+        assert_eq!(term("!'ab", true), (Kind::Exact, text("ab"), true));
+        assert_eq!(term("^ab", true), (Kind::Prefix, text("ab"), false));
+    }
+
+    #[test]
+    fn joins_terms_around_bars_into_groups() {
+        assert_eq!(groups("a | b c"), [vec!["a", "b"], vec!["c"]]);
+        assert_eq!(groups("a | b | c"), [vec!["a", "b", "c"]]);
+        // a bar with nothing before or after it joins nothing
+        assert_eq!(groups("| a b"), [vec!["a"], vec!["b"]]);
+        assert_eq!(groups("a b |"), [vec!["a"], vec!["b"]]);
+        assert!(groups("|").is_empty());
+        // nor does one next to a term that's only an operator
+        assert_eq!(groups("a | ^ b"), [vec!["a"], vec!["b"]]);
+    }
+
+    #[test]
+    fn narrows_only_while_typing_more_of_the_same_terms() {
+        let subsequence = extended(true);
+        assert!(subsequence.narrows("ab", "abc"));
+        assert!(subsequence.narrows("ab", "ab c"));
+        assert!(subsequence.narrows("ab", "ab$"));
+        assert!(!subsequence.narrows("ab", "b"));
+        assert!(!subsequence.narrows("ab", "ab | c"));
+        assert!(!subsequence.narrows("ab", "ab !c"));
+        assert!(!subsequence.narrows("ab$", "ab$c"));
+        assert!(!subsequence.narrows(r"ab\", r"ab\ c"));
+        // a suffix only narrows a matcher that takes any item with the chars in order
+        assert!(!extended(false).narrows("ab", "ab$"));
+    }
+}
@@ -0,0 +1,530 @@
+// A small regular expression engine for the regex matcher.
+//
+// Supports literals, `.`, classes (`[a-z]`, `[^...]`), `\d \w \s` and their negations,
+// anchors, groups, alternation and the `* + ? {n} {n,} {n,m}` quantifiers. Patterns are
+// compiled to a Thompson NFA and run as a Pike VM, reporting the leftmost-longest match.
+
+// Largest count a `{n,m}` quantifier may have, and most instructions a pattern may compile to,
+// since counted repeats are unrolled and every instruction is visited for every char.
+const MAX_REPEAT: usize = 1000;
+const MAX_PROGRAM: usize = 10_000;
+
+#[derive(Clone, Copy, Debug)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => c.is_alphanumeric() || c == '_',
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),
+}
+
+#[derive(Clone, Debug)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat(Box<Node>, usize, Option<usize>),
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn alt(&mut self) -> Option<Node> {
+        let mut branches = vec![self.concat()?];
+        while self.chars.peek() == Some(&'|') {
+            self.chars.next();
+            branches.push(self.concat()?);
+        }
+        Some(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alt(branches)
+        })
+    }
+
+    fn concat(&mut self) -> Option<Node> {
+        let mut nodes = Vec::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.repeat(atom)?);
+        }
+        Some(Node::Concat(nodes))
+    }
+
+    fn repeat(&mut self, mut node: Node) -> Option<Node> {
+        loop {
+            let (min, max) = match self.chars.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => {
+                    self.chars.next();
+                    let (min, max) = self.bounds()?;
+                    node = Node::Repeat(Box::new(node), min, max);
+                    continue;
+                }
+                _ => return Some(node),
+            };
+            self.chars.next();
+            // lazy quantifiers make no difference to a leftmost-longest match
+            if self.chars.peek() == Some(&'?') {
+                self.chars.next();
+            }
+            node = Node::Repeat(Box::new(node), min, max);
+        }
+    }
+
+    fn bounds(&mut self) -> Option<(usize, Option<usize>)> {
+        let min = self.number()?;
+        if min > MAX_REPEAT {
+            return None;
+        }
+        let max = match self.chars.next()? {
+            '}' => return Some((min, Some(min))),
+            ',' if self.chars.peek() == Some(&'}') => None,
+            ',' => Some(self.number()?),
+            _ => return None,
+        };
+        if self.chars.next()? != '}' || max.is_some_and(|max| max < min) {
+            return None;
+        }
+        if max.is_some_and(|max| max > MAX_REPEAT) {
+            return None;
+        }
+        Some((min, max))
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let mut digits = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_ascii_digit() {
+                break;
+            }
+            digits.push(c);
+            self.chars.next();
+        }
+        digits.parse().ok()
+    }
+
+    fn atom(&mut self) -> Option<Node> {
+        match self.chars.next()? {
+            '(' => {
+                // groups don't capture, so `(?:` is the same as `(`
+                if self.chars.peek() == Some(&'?') {
+                    self.chars.next();
+                    if self.chars.next()? != ':' {
+                        return None;
+                    }
+                }
+                let node = self.alt()?;
+                if self.chars.next()? != ')' {
+                    return None;
+                }
+                Some(node)
+            }
+            '[' => self.class().map(Node::Class),
+            '.' => Some(Node::Any),
+            '^' => Some(Node::Start),
+            '$' => Some(Node::End),
+            '\\' => match self.escape()? {
+                ClassItem::Range(c, _) => Some(Node::Char(c)),
+                perl => Some(Node::Class(Class {
+                    negated: false,
+                    items: vec![perl],
+                })),
+            },
+            '*' | '+' | '?' | '{' => None,
+            c => Some(Node::Char(c)),
+        }
+    }
+
+    fn escape(&mut self) -> Option<ClassItem> {
+        Some(match self.chars.next()? {
+            'd' => ClassItem::Perl(Perl::Digit, false),
+            'D' => ClassItem::Perl(Perl::Digit, true),
+            'w' => ClassItem::Perl(Perl::Word, false),
+            'W' => ClassItem::Perl(Perl::Word, true),
+            's' => ClassItem::Perl(Perl::Space, false),
+            'S' => ClassItem::Perl(Perl::Space, true),
+            't' => ClassItem::Range('\t', '\t'),
+            'n' => ClassItem::Range('\n', '\n'),
+            c if c.is_alphanumeric() => return None,
+            c => ClassItem::Range(c, c),
+        })
+    }
+
+    fn class(&mut self) -> Option<Class> {
+        let negated = self.chars.peek() == Some(&'^');
+        if negated {
+            self.chars.next();
+        }
+
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let item = match self.chars.next()? {
+                ']' if !first => break,
+                '\\' => self.escape()?,
+                c => ClassItem::Range(c, c),
+            };
+            first = false;
+
+            let item = match item {
+                ClassItem::Range(start, _) if self.chars.peek() == Some(&'-') => {
+                    self.chars.next();
+                    match self.chars.next()? {
+                        ']' => {
+                            items.push(ClassItem::Range(start, start));
+                            items.push(ClassItem::Range('-', '-'));
+                            break;
+                        }
+                        '\\' => match self.escape()? {
+                            ClassItem::Range(end, _) if start <= end => {
+                                ClassItem::Range(start, end)
+                            }
+                            _ => return None,
+                        },
+                        end if start <= end => ClassItem::Range(start, end),
+                        _ => return None,
+                    }
+                }
+                item => item,
+            };
+            items.push(item);
+        }
+
+        Some(Class { negated, items })
+    }
+}
+
+// `None` if the program grows past `MAX_PROGRAM`.
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Option<()> {
+    if program.len() > MAX_PROGRAM {
+        return None;
+    }
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program)?;
+            }
+        }
+        Node::Alt(branches) => {
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                if i + 1 < branches.len() {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(branch, program)?;
+                    jumps.push(program.len());
+                    program.push(Inst::Jmp(0));
+                    let next = program.len();
+                    program[split] = Inst::Split(split + 1, next);
+                } else {
+                    compile(branch, program)?;
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jmp(end);
+            }
+        }
+        Node::Repeat(node, min, max) => {
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jmp(split));
+                    let end = program.len();
+                    program[split] = Inst::Split(split + 1, end);
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(0, 0));
+                        compile(node, program)?;
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    Some(())
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Regex {
+    program: Vec<Inst>,
+    anchored: bool,
+    ignore_case: bool,
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+impl Regex {
+    /// `None` if the pattern is invalid or too large.
+    pub(crate) fn new(pattern: &str, ignore_case: bool) -> Option<Regex> {
+        let mut parser = Parser {
+            chars: pattern.chars().peekable(),
+        };
+        let node = parser.alt()?;
+        if parser.chars.next().is_some() {
+            return None;
+        }
+
+        let mut program = Vec::new();
+        compile(&node, &mut program)?;
+        if program.len() > MAX_PROGRAM {
+            return None;
+        }
+        program.push(Inst::Match);
+
+        let anchored = matches!(program.first(), Some(Inst::Start));
+        Some(Regex {
+            program,
+            anchored,
+            ignore_case,
+        })
+    }
+
+    fn char_eq(&self, a: char, b: char) -> bool {
+        a == b || (self.ignore_case && fold(a) == fold(b))
+    }
+
+    fn class_matches(&self, class: &Class, c: char) -> bool {
+        let found = class.items.iter().any(|item| match *item {
+            ClassItem::Range(start, end) => {
+                (start..=end).contains(&c)
+                    || (self.ignore_case
+                        && ((start..=end).contains(&fold(c))
+                            || c.to_uppercase().any(|u| (start..=end).contains(&u))))
+            }
+            ClassItem::Perl(perl, negated) => perl.matches(c) != negated,
+        });
+        found != class.negated
+    }
+
+    // Add the thread at `pc`, of a match from `start`, to `list`, following jumps, splits and
+    // anchors to the instructions that consume a char. Uses a stack of its own, as chains of
+    // splits can be long.
+    fn add_thread(
+        &self,
+        list: &mut Vec<(usize, usize)>,
+        seen: &mut [bool],
+        pc: usize,
+        start: usize,
+        pos: usize,
+        len: usize,
+    ) {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if seen[pc] {
+                continue;
+            }
+            seen[pc] = true;
+            match self.program[pc] {
+                Inst::Jmp(to) => stack.push(to),
+                Inst::Split(a, b) => {
+                    stack.push(b);
+                    stack.push(a);
+                }
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == len => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                _ => list.push((pc, start)),
+            }
+        }
+    }
+
+    /// Char range of the leftmost-longest match.
+    pub(crate) fn find(&self, input: &[char]) -> Option<(usize, usize)> {
+        self.find_at(input, 0)
+    }
+
+    /// Char range of the leftmost-longest match starting at `from` or after. Anchors still
+    /// match only at the ends of `input`.
+    pub(crate) fn find_at(&self, input: &[char], from: usize) -> Option<(usize, usize)> {
+        let len = input.len();
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        let mut seen = vec![false; self.program.len()];
+        let mut found: Option<(usize, usize)> = None;
+
+        for pos in from..=len {
+            // a new thread at each char, behind those that started before it, as a leading `.*?`
+            // would, until a match is found
+            if found.is_none() && (pos == from || !self.anchored) {
+                self.add_thread(&mut current, &mut seen, 0, pos, pos, len);
+            }
+            if current.is_empty() && (found.is_some() || self.anchored) {
+                break;
+            }
+            seen.iter_mut().for_each(|s| *s = false);
+            let c = input.get(pos).copied();
+            // threads are in order of where they started, so a match cuts off those behind it
+            for &(pc, start) in &current {
+                if found.is_some_and(|(first, _)| start > first) {
+                    break;
+                }
+                let advance = match (&self.program[pc], c) {
+                    (Inst::Match, _) => {
+                        found = Some((start, pos));
+                        false
+                    }
+                    (Inst::Char(expected), Some(c)) => self.char_eq(c, *expected),
+                    (Inst::Any, Some(_)) => true,
+                    (Inst::Class(class), Some(c)) => self.class_matches(class, c),
+                    _ => false,
+                };
+                if advance {
+                    self.add_thread(&mut next, &mut seen, pc + 1, start, pos + 1, len);
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, input: &str) -> Option<(usize, usize)> {
+        let input: Vec<_> = input.chars().collect();
+        Regex::new(pattern, false)
+            .expect("valid pattern")
+            .find(&input)
+    }
+
+    #[test]
+    fn finds_the_leftmost_longest_match() {
+        assert_eq!(find("b+", "abbbc"), Some((1, 4)));
+        assert_eq!(find("a|ab", "xab"), Some((1, 3)));
+        assert_eq!(find("[0-9]{2,3}", "a12345"), Some((1, 4)));
+        assert_eq!(find(r"\d+\s\w", "no 42 x"), Some((3, 7)));
+        assert_eq!(find("(?:ab)*c", "ababc"), Some((0, 5)));
+        assert_eq!(find("x", "abc"), None);
+    }
+
+    #[test]
+    fn anchors_match_only_at_the_ends() {
+        assert_eq!(find("^ab", "abab"), Some((0, 2)));
+        assert_eq!(find("^b", "ab"), None);
+        assert_eq!(find("b$", "abab"), Some((3, 4)));
+        assert_eq!(find("^$", ""), Some((0, 0)));
+    }
+
+    #[test]
+    fn prefers_the_leftmost_match_over_one_that_ends_first() {
+        assert_eq!(find("abcd|c", "abcd"), Some((0, 4)));
+        assert_eq!(find("b|abc", "xabc"), Some((1, 4)));
+        assert_eq!(find("a*", "baa"), Some((0, 0)));
+        assert_eq!(find("$", "ab"), Some((2, 2)));
+    }
+
+    #[test]
+    fn finds_from_an_offset_with_anchors_at_the_ends_of_the_input() {
+        let input: Vec<_> = "xaxa".chars().collect();
+        let regex = Regex::new("^x", false).unwrap();
+        assert_eq!(regex.find_at(&input, 0), Some((0, 1)));
+        assert_eq!(regex.find_at(&input, 1), None);
+        let regex = Regex::new("xa", false).unwrap();
+        assert_eq!(regex.find_at(&input, 1), Some((2, 4)));
+        assert_eq!(regex.find_at(&input, 4), None);
+        let regex = Regex::new("a$", false).unwrap();
+        assert_eq!(regex.find_at(&input, 2), Some((3, 4)));
+    }
+
+    #[test]
+    fn classes_and_case() {
+        assert_eq!(find("[^a-c]", "abcd"), Some((3, 4)));
+        assert_eq!(find("[a-]+", "x-a-"), Some((1, 4)));
+        let regex = Regex::new("[A-C]é", true).unwrap();
+        let input: Vec<_> = "xbÉ".chars().collect();
+        assert_eq!(regex.find(&input), Some((1, 3)));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        for pattern in ["(a", "a)", "[a", "*a", "a{2,1}", "a{", r"\q", "(?=a)"] {
+            assert!(Regex::new(pattern, false).is_none(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn rejects_patterns_too_large_to_run() {
+        assert!(Regex::new("a{1000}", false).is_some());
+        assert!(Regex::new("a{1001}", false).is_none());
+        assert!(Regex::new("h(a?){300000}", false).is_none());
+        assert!(Regex::new("a{300000000}", false).is_none());
+        // small repeats that multiply past the limit
+        assert!(Regex::new("((a{100}){100}){100}", false).is_none());
+    }
+
+    #[test]
+    fn long_chains_of_optional_chars_match() {
+        let pattern = format!("h{}", "a?".repeat(MAX_PROGRAM / 3));
+        let input = format!("h{}", "a".repeat(100));
+        let input: Vec<_> = input.chars().collect();
+        let regex = Regex::new(&pattern, false).unwrap();
+        assert_eq!(regex.find(&input), Some((0, 101)));
+    }
+}
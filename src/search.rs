@@ -0,0 +1,442 @@
+use std::{
+    cmp::Ordering,
+    sync::{
+        atomic::{self, AtomicUsize},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use rayon::prelude::*;
+
+use crate::waker::Waker;
+use crate::worker::{Reply, Request, Worker};
+use crate::{tiebreak, Item, Matcher, RankedItem, Tiebreak};
+
+// Results ordered by each rayon task before the best of each are merged.
+const TOP_CHUNK: usize = 16_384;
+
+/// What an item is searched by, worked out once when it arrives rather than on every search.
+#[derive(Debug)]
+pub(crate) struct SearchKey {
+    pub(crate) text: String,
+    // chars in `text`
+    pub(crate) length: usize,
+    bag: CharBag,
+}
+
+impl SearchKey {
+    pub(crate) fn new<T>(item: &T) -> SearchKey
+    where
+        T: Item,
+    {
+        let text = item.search_text().into_owned();
+        let length = if text.is_ascii() {
+            text.len()
+        } else {
+            text.chars().count()
+        };
+        let bag = CharBag::of(&text);
+        SearchKey { text, length, bag }
+    }
+}
+
+// The chars of some text, ignoring case, each setting one of 64 bits. Text can only contain
+// all the chars of another if its bag has all of the other's bits.
+#[derive(Clone, Copy, Debug, Default)]
+struct CharBag(u64);
+
+impl CharBag {
+    fn of(text: &str) -> CharBag {
+        if text.is_ascii() {
+            let bits = text
+                .bytes()
+                .map(|b| CharBag::bit(b.to_ascii_lowercase() as char));
+            return CharBag(bits.fold(0, |bag, bit| bag | bit));
+        }
+        let bits = text
+            .chars()
+            .map(|c| CharBag::bit(c.to_lowercase().next().unwrap_or(c)));
+        CharBag(bits.fold(0, |bag, bit| bag | bit))
+    }
+
+    // Letters and digits get a bit each, and everything else shares the rest.
+    fn bit(c: char) -> u64 {
+        let bit = match c {
+            'a'..='z' => c as u32 - 'a' as u32,
+            '0'..='9' => 26 + c as u32 - '0' as u32,
+            _ => 36 + c as u32 % 28,
+        };
+        1 << bit
+    }
+
+    fn contains(self, other: CharBag) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// An item matching the query, with just enough to order it among the other results.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Match {
+    // position of the item in the list
+    pub(crate) index: usize,
+    pub(crate) score: i64,
+    // chars in the search text
+    pub(crate) length: usize,
+    // first and last matched chars, only found for the tiebreaks that need them
+    pub(crate) begin: Option<usize>,
+    pub(crate) end: Option<usize>,
+}
+
+/// Matches items against one query.
+pub(crate) struct Scoring<'a> {
+    matcher: &'a dyn Matcher,
+    query: &'a str,
+    // chars an item needs to have any chance of matching
+    required: CharBag,
+    // whether the tiebreaks need to know where items matched
+    positions: bool,
+}
+
+impl<'a> Scoring<'a> {
+    pub(crate) fn new(
+        matcher: &'a dyn Matcher,
+        query: &'a str,
+        tiebreaks: &[Tiebreak],
+    ) -> Scoring<'a> {
+        Scoring {
+            matcher,
+            query,
+            required: CharBag::of(&matcher.required(query)),
+            positions: tiebreaks
+                .iter()
+                .any(|t| matches!(t, Tiebreak::Begin | Tiebreak::End)),
+        }
+    }
+
+    /// How the item at `index` matches the query, if it does. Matched chars are only found
+    /// when a tiebreak needs them, since the matchers are quicker without.
+    pub(crate) fn score(&self, index: usize, key: &SearchKey) -> Option<Match> {
+        if !key.bag.contains(self.required) {
+            return None;
+        }
+        let (score, begin, end) = if self.positions {
+            let (score, indices) = self.matcher.match_indices(&key.text, self.query)?;
+            (score, indices.first().copied(), indices.last().copied())
+        } else {
+            (self.matcher.score(&key.text, self.query)?, None, None)
+        };
+        Some(Match {
+            index,
+            score,
+            length: key.length,
+            begin,
+            end,
+        })
+    }
+}
+
+/// Results of the current query, found by a `Worker` in the background.
+///
+/// The results of the previous query stay until the first of the new ones arrive, and are
+/// only put in order as far down as they're looked at.
+pub(crate) struct Search {
+    requests: Sender<Request>,
+    replies: Receiver<Reply>,
+    latest: Arc<AtomicUsize>,
+    tiebreaks: Vec<Tiebreak>,
+    query: String,
+    generation: usize,
+    // requests of this generation the worker hasn't finished
+    outstanding: usize,
+    // items sent to the worker
+    items: usize,
+    // whether any results of this generation have arrived
+    fresh: bool,
+    // every match, in input order
+    matches: Arc<Vec<Match>>,
+    // the best matches in order, found as far down as has been looked
+    top: Vec<Match>,
+    // items the running search has looked at, out of how many
+    progress: (usize, usize),
+}
+
+impl Search {
+    /// Start a worker searching with `matcher`, which wakes `waker` whenever it has news.
+    pub(crate) fn new(matcher: Arc<dyn Matcher>, tiebreaks: &[Tiebreak], waker: Waker) -> Search {
+        let (requests, worker_requests) = mpsc::channel();
+        let (worker_replies, replies) = mpsc::channel();
+        let latest = Arc::new(AtomicUsize::new(0));
+        let worker = Worker::new(
+            matcher,
+            tiebreaks,
+            latest.clone(),
+            worker_requests,
+            worker_replies,
+            waker,
+        );
+        thread::spawn(move || worker.run());
+        Search {
+            requests,
+            replies,
+            latest,
+            tiebreaks: tiebreaks.to_vec(),
+            query: String::new(),
+            generation: 0,
+            outstanding: 0,
+            items: 0,
+            fresh: true,
+            matches: Arc::default(),
+            top: Vec::new(),
+            progress: (0, 0),
+        }
+    }
+
+    fn request(&mut self, request: Request) {
+        if self.requests.send(request).is_ok() {
+            self.outstanding += 1;
+        }
+    }
+
+    /// Search for `query`, giving up on any earlier search.
+    pub(crate) fn update(&mut self, query: &str) {
+        self.generation += 1;
+        self.latest
+            .store(self.generation, atomic::Ordering::Relaxed);
+        self.query = query.to_string();
+        self.outstanding = 0;
+        self.fresh = query.is_empty();
+        if self.fresh {
+            self.matches = Arc::default();
+            self.top.clear();
+        }
+        self.progress = (0, self.items);
+        self.request(Request::Search(query.to_string(), self.generation));
+    }
+
+    /// Search items that arrived, in order, for the current query.
+    pub(crate) fn extend(&mut self, keys: Vec<Arc<SearchKey>>) {
+        self.items += keys.len();
+        self.request(Request::Items(keys));
+    }
+
+    /// Tell the worker no more items will arrive, so it can start searching ahead.
+    pub(crate) fn end(&mut self) {
+        // the worker is only gone once the picker has closed
+        let _ = self.requests.send(Request::End);
+    }
+
+    /// Whether results of the current query are still to come.
+    pub(crate) fn busy(&self) -> bool {
+        self.outstanding > 0
+    }
+
+    /// Items the running search has looked at, out of how many.
+    pub(crate) fn progress(&self) -> (usize, usize) {
+        self.progress
+    }
+
+    /// Take the results that have arrived, returning whether there were any.
+    pub(crate) fn receive(&mut self) -> bool {
+        let mut received = false;
+        loop {
+            match self.replies.try_recv() {
+                Ok(reply) => self.apply(reply),
+                Err(TryRecvError::Empty) => return received,
+                // the worker is gone, so nothing more is coming
+                Err(TryRecvError::Disconnected) => {
+                    self.outstanding = 0;
+                    return received;
+                }
+            }
+            received = true;
+        }
+    }
+
+    /// Wait for the rest of the results.
+    pub(crate) fn finish(&mut self) {
+        while self.busy() {
+            match self.replies.recv() {
+                Ok(reply) => self.apply(reply),
+                // the worker is gone, so nothing more is coming
+                Err(_) => self.outstanding = 0,
+            }
+        }
+    }
+
+    fn apply(&mut self, reply: Reply) {
+        match reply {
+            Reply::Found {
+                generation,
+                matches,
+                searched,
+                total,
+            } if generation == self.generation => {
+                if self.fresh {
+                    Arc::make_mut(&mut self.matches).extend(matches.iter());
+                } else {
+                    self.matches = matches;
+                    self.fresh = true;
+                }
+                self.top.clear();
+                self.progress = (searched, total);
+            }
+            Reply::Finished(generation) if generation == self.generation => {
+                self.outstanding -= 1;
+            }
+            _ => {}
+        }
+    }
+
+    /// Number of results for the current query.
+    pub(crate) fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// The best `n` results, best first.
+    pub(crate) fn ranked(&mut self, n: usize) -> &[Match] {
+        let n = n.min(self.len());
+        if self.top.len() < n {
+            // look further than asked, so scrolling down doesn't rank again on every row
+            let k = n.max(self.top.len() * 2).min(self.len());
+            self.top = top(&self.matches, k, &self.tiebreaks);
+        }
+        &self.top[..n]
+    }
+
+    /// Rows `offset..offset + rows` of the results, with the chars that matched.
+    pub(crate) fn page<T>(
+        &mut self,
+        matcher: &dyn Matcher,
+        list: &[RankedItem<T>],
+        offset: usize,
+        rows: usize,
+    ) -> Vec<RankedItem<T>>
+    where
+        T: Item,
+    {
+        let query = self.query.clone();
+        self.ranked(offset + rows)
+            .iter()
+            .skip(offset)
+            .map(|m| {
+                let mut row = list[m.index].clone();
+                row.indices = matcher
+                    .match_indices(&row.key.text, &query)
+                    .map(|(_, indices)| indices)
+                    .unwrap_or_default();
+                row.score = Some(m.score);
+                row
+            })
+            .collect()
+    }
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        // stop the worker searching for nobody
+        self.latest.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+}
+
+// The best `k` of `matches` in order. Each chunk keeps its own best `k`, then those are merged.
+// The worker searches on a rayon pool of its own, so this never waits behind a search.
+fn top(matches: &[Match], k: usize, tiebreaks: &[Tiebreak]) -> Vec<Match> {
+    if k == 0 {
+        return Vec::new();
+    }
+    let compare = |a: &Match, b: &Match| tiebreak::compare(a, b, tiebreaks);
+    matches
+        .par_chunks(TOP_CHUNK)
+        .map(|chunk| {
+            let mut best = chunk.to_vec();
+            if best.len() > k {
+                best.select_nth_unstable_by(k - 1, compare);
+                best.truncate(k);
+            }
+            best.sort_unstable_by(compare);
+            best
+        })
+        .reduce(Vec::new, |a, b| merge(a, b, k, compare))
+}
+
+// The first `k` of two ordered lists, in order.
+fn merge(
+    a: Vec<Match>,
+    b: Vec<Match>,
+    k: usize,
+    compare: impl Fn(&Match, &Match) -> Ordering,
+) -> Vec<Match> {
+    let mut merged = Vec::with_capacity(k.min(a.len() + b.len()));
+    let (mut a, mut b) = (a.into_iter().peekable(), b.into_iter().peekable());
+    while merged.len() < k {
+        let next = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) if compare(x, y) == Ordering::Greater => b.next(),
+            (Some(_), _) => a.next(),
+            (None, _) => b.next(),
+        };
+        match next {
+            Some(m) => merged.push(m),
+            None => break,
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(scores: &[i64]) -> Vec<Match> {
+        let matches = scores.iter().enumerate().map(|(index, &score)| Match {
+            index,
+            score,
+            length: 1,
+            begin: None,
+            end: None,
+        });
+        matches.collect()
+    }
+
+    fn indices(matches: &[Match]) -> Vec<usize> {
+        matches.iter().map(|m| m.index).collect()
+    }
+
+    #[test]
+    fn ranks_the_best_k() {
+        let all = matches(&[1, 5, 3, 4, 2]);
+        assert!(top(&all, 0, &[Tiebreak::Index]).is_empty());
+        assert_eq!(indices(&top(&all, 2, &[Tiebreak::Index])), [1, 3]);
+        // asking for more than there are ranks them all
+        assert_eq!(indices(&top(&all, 10, &[Tiebreak::Index])), [1, 3, 2, 4, 0]);
+    }
+
+    #[test]
+    fn breaks_ties_across_chunks() {
+        // equal scores on both sides of each chunk boundary, with better ones in the last chunk
+        let mut scores = vec![1; TOP_CHUNK * 2 + 10];
+        scores[TOP_CHUNK * 2 + 5] = 2;
+        let all = matches(&scores);
+        let best = top(&all, 4, &[Tiebreak::Index]);
+        let expected = [TOP_CHUNK * 2 + 5, 0, 1, 2];
+        assert_eq!(indices(&best), expected);
+
+        let all = matches(&vec![1; TOP_CHUNK * 3]);
+        let best = top(&all, TOP_CHUNK + 1, &[Tiebreak::Index]);
+        assert_eq!(indices(&best), (0..=TOP_CHUNK).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn merges_the_first_k_in_order() {
+        let compare = |a: &Match, b: &Match| tiebreak::compare(a, b, &[Tiebreak::Index]);
+        let (a, b) = (matches(&[9, 7, 3]), matches(&[8, 7, 1]));
+        let merged = merge(a.clone(), b.clone(), 4, compare);
+        let scores: Vec<_> = merged.iter().map(|m| m.score).collect();
+        assert_eq!(scores, [9, 8, 7, 7]);
+        // ties go to the first list, which holds the earlier chunk
+        assert_eq!(indices(&merged[2..]), [1, 1]);
+        assert!(merge(a.clone(), b.clone(), 0, compare).is_empty());
+        assert_eq!(merge(a, Vec::new(), 5, compare).len(), 3);
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::waker::Waker;
+
+// Where the picker leaves a waker while it waits for items, for whatever sends them to take and
+// wake on the next one.
+pub(crate) type Waiting = Arc<Mutex<Option<Waker>>>;
+
+/// Items that keep arriving while the picker is open.
+///
+/// The picker is drawn straight away and shows a loading indicator until the source is exhausted,
+/// that is until every `Sender` of the channel has been dropped.
+#[derive(Debug)]
+pub struct Source<T> {
+    pub(crate) items: Receiver<T>,
+    // for sources that wake the picker as items arrive, rather than being checked every frame
+    pub(crate) waiting: Option<Waiting>,
+}
+
+impl<T> Source<T>
+where
+    T: Send + 'static,
+{
+    /// Drain `iter` on a background thread.
+    pub fn spawn<I>(iter: I) -> Source<T>
+    where
+        I: IntoIterator<Item = T> + Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        let waiting = Waiting::default();
+        let waiter = waiting.clone();
+        thread::spawn(move || {
+            for item in iter {
+                if sender.send(item).is_err() {
+                    // picker was closed
+                    return;
+                }
+                wake(&waiter);
+            }
+            // the picker finds the end once the sender is gone
+            drop(sender);
+            wake(&waiter);
+        });
+        Source {
+            items: receiver,
+            waiting: Some(waiting),
+        }
+    }
+
+    /// A source fed by sending on the returned `Sender`, which never blocks, so items can be
+    /// forwarded from an async stream as they arrive. The picker checks for them every frame.
+    pub fn channel() -> (Sender<T>, Source<T>) {
+        let (sender, receiver) = channel();
+        (sender, Source::from(receiver))
+    }
+}
+
+fn wake(waiting: &Waiting) {
+    let waker = waiting.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+impl<T> From<Receiver<T>> for Source<T> {
+    fn from(receiver: Receiver<T>) -> Source<T> {
+        Source {
+            items: receiver,
+            waiting: None,
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for Source<T> {
+    /// A source that already holds every item.
+    fn from(items: Vec<T>) -> Source<T> {
+        let (sender, receiver) = channel();
+        for item in items {
+            // the receiver is still here, so sending can't fail
+            let _ = sender.send(item);
+        }
+        Source::from(receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::TryRecvError,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    // Wait for `waiting` to be woken, as the picker does, after `then`.
+    fn woken(waiting: &Waiting, then: impl FnOnce()) -> bool {
+        let waker = Waker::default();
+        *waiting.lock().unwrap() = Some(waker.clone());
+        let start = Instant::now();
+        then();
+        waker.wait(start + Duration::from_secs(5));
+        start.elapsed() < Duration::from_secs(1)
+    }
+
+    #[test]
+    fn spawned_sources_wake_the_picker_waiting_for_items() {
+        let (sender, items) = channel();
+        let source = Source::spawn(items);
+        let waiting = source.waiting.clone().unwrap();
+
+        assert!(woken(&waiting, || sender.send(1).unwrap()));
+        assert_eq!(source.items.try_recv(), Ok(1));
+        assert!(woken(&waiting, || drop(sender)));
+        assert_eq!(source.items.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn item_sources_end_after_their_items() {
+        let source = Source::from(vec![1, 2]);
+        assert!(source.waiting.is_none());
+        assert_eq!(source.items.try_iter().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(source.items.try_recv(), Err(TryRecvError::Disconnected));
+
+        let (sender, source) = Source::channel();
+        sender.send(1).unwrap();
+        assert_eq!(source.items.try_recv(), Ok(1));
+        assert_eq!(source.items.try_recv(), Err(TryRecvError::Empty));
+        drop(sender);
+        assert_eq!(source.items.try_recv(), Err(TryRecvError::Disconnected));
+    }
+}
@@ -0,0 +1,192 @@
+use std::env;
+
+use crossterm::style::{Attribute, Color, ContentStyle};
+
+/// How each part of the picker is drawn.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    /// Text in front of the query.
+    pub prompt: ContentStyle,
+    pub query: ContentStyle,
+    /// Spinner and item count while items are loading.
+    pub info: ContentStyle,
+    pub header: ContentStyle,
+    /// Marker in front of the highlighted result.
+    pub pointer: ContentStyle,
+    /// Line numbers in front of the other results.
+    pub number: ContentStyle,
+    /// Marker in front of results marked in multi-select mode.
+    pub marker: ContentStyle,
+    /// The highlighted result, usually just a background.
+    pub selected: ContentStyle,
+    /// Characters matching the query.
+    pub matched: ContentStyle,
+    /// Lines separating the preview from the results.
+    pub border: ContentStyle,
+    /// Draw each letter of the query, and the characters it matched, in a random color of its own.
+    pub letter_colors: bool,
+    /// Draw items in the styles of the spans `Item::display` splits them into.
+    pub item_styles: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            prompt: ContentStyle::new()
+                .foreground(Color::Cyan)
+                .attribute(Attribute::SlowBlink)
+                .attribute(Attribute::Bold),
+            query: ContentStyle::new().foreground(Color::White),
+            info: ContentStyle::new().foreground(Color::DarkGrey),
+            header: ContentStyle::new().foreground(Color::DarkGreen),
+            pointer: ContentStyle::new()
+                .foreground(Color::Red)
+                .attribute(Attribute::Bold),
+            number: ContentStyle::new().foreground(Color::Blue),
+            marker: ContentStyle::new()
+                .foreground(Color::Red)
+                .attribute(Attribute::Bold),
+            selected: ContentStyle::new().background(Color::DarkGrey),
+            matched: ContentStyle::new()
+                .foreground(Color::White)
+                .attribute(Attribute::Underlined)
+                .attribute(Attribute::Bold)
+                .attribute(Attribute::Italic),
+            border: ContentStyle::new().foreground(Color::DarkGrey),
+            letter_colors: true,
+            item_styles: true,
+        }
+    }
+}
+
+impl Theme {
+    /// For dark terminals, without the random letter colors.
+    pub fn dark() -> Theme {
+        Theme {
+            prompt: ContentStyle::new().foreground(Color::Blue),
+            query: ContentStyle::new(),
+            info: ContentStyle::new().foreground(Color::Grey),
+            header: ContentStyle::new().foreground(Color::Cyan),
+            pointer: ContentStyle::new().foreground(Color::Magenta),
+            number: ContentStyle::new().foreground(Color::DarkGrey),
+            marker: ContentStyle::new().foreground(Color::Magenta),
+            selected: ContentStyle::new()
+                .background(Color::AnsiValue(236))
+                .attribute(Attribute::Bold),
+            matched: ContentStyle::new().foreground(Color::Green),
+            border: ContentStyle::new().foreground(Color::DarkGrey),
+            letter_colors: false,
+            item_styles: true,
+        }
+    }
+
+    /// For light terminals, without the random letter colors.
+    pub fn light() -> Theme {
+        Theme {
+            prompt: ContentStyle::new().foreground(Color::DarkBlue),
+            query: ContentStyle::new(),
+            info: ContentStyle::new().foreground(Color::DarkGrey),
+            header: ContentStyle::new().foreground(Color::DarkCyan),
+            pointer: ContentStyle::new().foreground(Color::DarkRed),
+            number: ContentStyle::new().foreground(Color::Grey),
+            marker: ContentStyle::new().foreground(Color::DarkRed),
+            selected: ContentStyle::new()
+                .background(Color::AnsiValue(254))
+                .attribute(Attribute::Bold),
+            matched: ContentStyle::new().foreground(Color::DarkMagenta),
+            border: ContentStyle::new().foreground(Color::Grey),
+            letter_colors: false,
+            item_styles: true,
+        }
+    }
+
+    /// No colors at all, only bold and reverse video, and items drawn as plain text.
+    pub fn monochrome() -> Theme {
+        let bold = ContentStyle::new().attribute(Attribute::Bold);
+        Theme {
+            prompt: bold.clone(),
+            query: ContentStyle::new(),
+            info: ContentStyle::new(),
+            header: ContentStyle::new(),
+            pointer: bold.clone(),
+            number: ContentStyle::new(),
+            marker: bold.clone(),
+            selected: ContentStyle::new().attribute(Attribute::Reverse),
+            matched: bold.attribute(Attribute::Underlined),
+            border: ContentStyle::new(),
+            letter_colors: false,
+            item_styles: false,
+        }
+    }
+
+    /// Whether the `NO_COLOR` environment variable asks for no colors.
+    pub fn no_color() -> bool {
+        env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+    }
+}
+
+/// `style` drawn over `base`: its colors win where set, and the attributes of both apply.
+pub(crate) fn layer(base: &ContentStyle, style: &ContentStyle) -> ContentStyle {
+    let mut layered = base.clone();
+    if style.foreground_color.is_some() {
+        layered.foreground_color = style.foreground_color;
+    }
+    if style.background_color.is_some() {
+        layered.background_color = style.background_color;
+    }
+    layered.attributes.extend(style.attributes.iter().copied());
+    layered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monochrome_has_no_colors() {
+        let theme = Theme::monochrome();
+        let styles = [
+            &theme.prompt,
+            &theme.query,
+            &theme.info,
+            &theme.header,
+            &theme.pointer,
+            &theme.number,
+            &theme.marker,
+            &theme.selected,
+            &theme.matched,
+            &theme.border,
+        ];
+        for style in styles {
+            assert_eq!(style.foreground_color, None);
+            assert_eq!(style.background_color, None);
+        }
+        assert!(!theme.letter_colors && !theme.item_styles);
+    }
+
+    #[test]
+    fn layers_colors_over_the_base_and_adds_attributes() {
+        let base = ContentStyle::new()
+            .foreground(Color::Red)
+            .background(Color::Blue)
+            .attribute(Attribute::Bold);
+        let style = ContentStyle::new()
+            .foreground(Color::Green)
+            .attribute(Attribute::Italic);
+        let layered = layer(&base, &style);
+        assert_eq!(layered.foreground_color, Some(Color::Green));
+        assert_eq!(layered.background_color, Some(Color::Blue));
+        assert_eq!(layered.attributes, vec![Attribute::Bold, Attribute::Italic]);
+    }
+
+    #[test]
+    fn no_color_needs_a_value() {
+        // the only test here touching the environment
+        env::set_var("NO_COLOR", "1");
+        assert!(Theme::no_color());
+        env::set_var("NO_COLOR", "");
+        assert!(!Theme::no_color());
+        env::remove_var("NO_COLOR");
+        assert!(!Theme::no_color());
+    }
+}
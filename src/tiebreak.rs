@@ -0,0 +1,79 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use crate::search::Match;
+use crate::ParseError;
+
+/// What orders results with the same score, as with fzf's `--tiebreak`.
+///
+/// Each result is compared by score first, then by each tiebreak in turn, and finally by input
+/// order, so the order of the results never depends on how they were ranked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Tiebreak {
+    /// Shorter items first.
+    Length,
+    /// Items matching earlier first.
+    Begin,
+    /// Items whose match ends earlier first.
+    End,
+    /// Items earlier in the input first.
+    Index,
+}
+
+const TIEBREAK_NAMES: &[(&str, Tiebreak)] = &[
+    ("length", Tiebreak::Length),
+    ("begin", Tiebreak::Begin),
+    ("end", Tiebreak::End),
+    ("index", Tiebreak::Index),
+];
+
+impl Tiebreak {
+    fn compare(self, a: &Match, b: &Match) -> Ordering {
+        match self {
+            Tiebreak::Length => a.length.cmp(&b.length),
+            Tiebreak::Begin => a.begin.cmp(&b.begin),
+            Tiebreak::End => a.end.cmp(&b.end),
+            Tiebreak::Index => a.index.cmp(&b.index),
+        }
+    }
+
+    /// Parse tiebreaks separated by commas, such as `length,begin`.
+    pub fn parse_chain(s: &str) -> Result<Vec<Tiebreak>, ParseError> {
+        s.split(',').map(str::parse).collect()
+    }
+}
+
+impl FromStr for Tiebreak {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Tiebreak, ParseError> {
+        TIEBREAK_NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|&(_, tiebreak)| tiebreak)
+            .ok_or_else(|| ParseError(format!("unknown tiebreak: {}", s)))
+    }
+}
+
+impl fmt::Display for Tiebreak {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = TIEBREAK_NAMES
+            .iter()
+            .find(|(_, tiebreak)| tiebreak == self)
+            .map_or("", |(name, _)| *name);
+        f.write_str(name)
+    }
+}
+
+// Whether `a` is shown before `b`: higher scores first, then by `tiebreaks`, then input order.
+pub(crate) fn compare(a: &Match, b: &Match, tiebreaks: &[Tiebreak]) -> Ordering {
+    b.score
+        .cmp(&a.score)
+        .then_with(|| {
+            tiebreaks
+                .iter()
+                .map(|tiebreak| tiebreak.compare(a, b))
+                .find(|&ord| ord != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        })
+        .then_with(|| a.index.cmp(&b.index))
+}
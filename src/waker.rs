@@ -0,0 +1,43 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Instant,
+};
+
+/// Wakes the event loop from other threads, such as when results arrive.
+///
+/// Wakes that come while nobody waits aren't lost, and any number of them wake a single wait.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Waker(Arc<(Mutex<bool>, Condvar, AtomicBool)>);
+
+impl Waker {
+    pub(crate) fn wake(&self) {
+        let (woken, wakes, _) = &*self.0;
+        *woken.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        wakes.notify_one();
+    }
+
+    /// Wake the event loop to close the picker, as if it was aborted.
+    #[cfg(feature = "async")]
+    pub(crate) fn close(&self) {
+        self.0 .2.store(true, Ordering::Relaxed);
+        self.wake();
+    }
+
+    pub(crate) fn closed(&self) -> bool {
+        self.0 .2.load(Ordering::Relaxed)
+    }
+
+    /// Wait until woken or `deadline`, whichever is first.
+    pub(crate) fn wait(&self, deadline: Instant) {
+        let (woken, wakes, _) = &*self.0;
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let woken = woken.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut woken, _) = wakes
+            .wait_timeout_while(woken, timeout, |woken| !*woken)
+            .unwrap_or_else(|e| e.into_inner());
+        *woken = false;
+    }
+}
@@ -0,0 +1,273 @@
+// Display widths and grapheme clusters, close enough to what terminals do for truncating
+// and placing the cursor.
+//
+// The tables cover combining marks, zero width format characters, East Asian wide and fullwidth
+// characters and emoji. Grapheme clusters are a base character with any combining characters,
+// variation selectors and emoji modifiers after it, characters joined to it with a zero width
+// joiner, and pairs of regional indicators making up a flag.
+
+const ZERO_WIDTH: &[(u32, u32)] = &[
+    (0x0300, 0x036F),
+    (0x0483, 0x0489),
+    (0x0591, 0x05BD),
+    (0x05BF, 0x05BF),
+    (0x05C1, 0x05C2),
+    (0x05C4, 0x05C5),
+    (0x05C7, 0x05C7),
+    (0x0610, 0x061A),
+    (0x064B, 0x065F),
+    (0x0670, 0x0670),
+    (0x06D6, 0x06DC),
+    (0x06DF, 0x06E4),
+    (0x06E7, 0x06E8),
+    (0x06EA, 0x06ED),
+    (0x0711, 0x0711),
+    (0x0730, 0x074A),
+    (0x07A6, 0x07B0),
+    (0x0900, 0x0902),
+    (0x093A, 0x093A),
+    (0x093C, 0x093C),
+    (0x0941, 0x0948),
+    (0x094D, 0x094D),
+    (0x0951, 0x0957),
+    (0x0962, 0x0963),
+    (0x0981, 0x0981),
+    (0x09BC, 0x09BC),
+    (0x09C1, 0x09C4),
+    (0x09CD, 0x09CD),
+    (0x0E31, 0x0E31),
+    (0x0E34, 0x0E3A),
+    (0x0E47, 0x0E4E),
+    (0x0EB1, 0x0EB1),
+    (0x0EB4, 0x0EBC),
+    (0x0EC8, 0x0ECD),
+    (0x1160, 0x11FF),
+    (0x1AB0, 0x1AFF),
+    (0x1DC0, 0x1DFF),
+    (0x200B, 0x200F),
+    (0x202A, 0x202E),
+    (0x2060, 0x2064),
+    (0x20D0, 0x20FF),
+    (0x302A, 0x302D),
+    (0x3099, 0x309A),
+    (0xFE00, 0xFE0F),
+    (0xFE20, 0xFE2F),
+    (0xFEFF, 0xFEFF),
+    (0x1F3FB, 0x1F3FF),
+    (0xE0000, 0xE007F),
+    (0xE0100, 0xE01EF),
+];
+
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115F),
+    (0x231A, 0x231B),
+    (0x2329, 0x232A),
+    (0x23E9, 0x23EC),
+    (0x23F0, 0x23F0),
+    (0x23F3, 0x23F3),
+    (0x25FD, 0x25FE),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267F, 0x267F),
+    (0x2693, 0x2693),
+    (0x26A1, 0x26A1),
+    (0x26AA, 0x26AB),
+    (0x26BD, 0x26BE),
+    (0x26C4, 0x26C5),
+    (0x26CE, 0x26CE),
+    (0x26D4, 0x26D4),
+    (0x26EA, 0x26EA),
+    (0x26F2, 0x26F3),
+    (0x26F5, 0x26F5),
+    (0x26FA, 0x26FA),
+    (0x26FD, 0x26FD),
+    (0x2705, 0x2705),
+    (0x270A, 0x270B),
+    (0x2728, 0x2728),
+    (0x274C, 0x274C),
+    (0x274E, 0x274E),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27B0, 0x27B0),
+    (0x27BF, 0x27BF),
+    (0x2B1B, 0x2B1C),
+    (0x2B50, 0x2B50),
+    (0x2B55, 0x2B55),
+    (0x2E80, 0x303E),
+    (0x3041, 0x33FF),
+    (0x3400, 0x4DBF),
+    (0x4E00, 0x9FFF),
+    (0xA000, 0xA4CF),
+    (0xA960, 0xA97F),
+    (0xAC00, 0xD7A3),
+    (0xF900, 0xFAFF),
+    (0xFE10, 0xFE19),
+    (0xFE30, 0xFE6F),
+    (0xFF00, 0xFF60),
+    (0xFFE0, 0xFFE6),
+    (0x16FE0, 0x16FE4),
+    (0x17000, 0x18AFF),
+    (0x1B000, 0x1B2FF),
+    (0x1F004, 0x1F004),
+    (0x1F0CF, 0x1F0CF),
+    (0x1F18E, 0x1F18E),
+    (0x1F191, 0x1F19A),
+    (0x1F200, 0x1F251),
+    (0x1F300, 0x1F64F),
+    (0x1F680, 0x1F6FF),
+    (0x1F7E0, 0x1F7EB),
+    (0x1F90C, 0x1F9FF),
+    (0x1FA70, 0x1FAFF),
+    (0x20000, 0x2FFFD),
+    (0x30000, 0x3FFFD),
+];
+
+const ZWJ: char = '\u{200D}';
+const EMOJI_PRESENTATION: char = '\u{FE0F}';
+
+fn in_table(table: &[(u32, u32)], c: char) -> bool {
+    let c = c as u32;
+    table
+        .binary_search_by(|&(start, end)| {
+            if end < c {
+                std::cmp::Ordering::Less
+            } else if start > c {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+fn regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+// Whether `c` belongs to the grapheme before it rather than starting its own.
+fn extends(c: char) -> bool {
+    c == ZWJ || in_table(ZERO_WIDTH, c)
+}
+
+/// Columns taken by `c` on its own.
+pub(crate) fn char_width(c: char) -> usize {
+    if c.is_control() || c == ZWJ || in_table(ZERO_WIDTH, c) {
+        0
+    } else if in_table(WIDE, c) {
+        2
+    } else {
+        1
+    }
+}
+
+/// A grapheme cluster of a string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Grapheme<'a> {
+    pub(crate) text: &'a str,
+    /// Index of its first char in the whole string.
+    pub(crate) start: usize,
+    /// Number of chars in it.
+    pub(crate) len: usize,
+    pub(crate) width: usize,
+}
+
+pub(crate) fn graphemes(text: &str) -> Vec<Grapheme<'_>> {
+    let mut graphemes: Vec<Grapheme> = Vec::new();
+    let mut joined = false;
+    for (index, (offset, c)) in text.char_indices().enumerate() {
+        let pair = graphemes.last().is_some_and(|last| {
+            last.len == 1 && last.text.chars().all(regional_indicator) && regional_indicator(c)
+        });
+        match graphemes.last_mut() {
+            Some(last) if joined || pair || extends(c) => {
+                last.text = &text[offset - last.text.len()..offset + c.len_utf8()];
+                last.len += 1;
+                if pair || (c == EMOJI_PRESENTATION && last.width == 1) {
+                    last.width = 2;
+                }
+            }
+            _ => graphemes.push(Grapheme {
+                text: &text[offset..offset + c.len_utf8()],
+                start: index,
+                len: 1,
+                width: char_width(c),
+            }),
+        }
+        joined = c == ZWJ;
+    }
+    graphemes
+}
+
+/// Columns taken by `text`.
+pub(crate) fn width(text: &str) -> usize {
+    graphemes(text).iter().map(|g| g.width).sum()
+}
+
+/// The longest start of `text` made of whole graphemes fitting in `width` columns.
+pub(crate) fn truncate(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    let mut end = 0;
+    for grapheme in graphemes(text) {
+        if used + grapheme.width > width {
+            break;
+        }
+        used += grapheme.width;
+        end += grapheme.text.len();
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // text, first char and width of each grapheme
+    fn split(text: &str) -> Vec<(&str, usize, usize)> {
+        graphemes(text)
+            .into_iter()
+            .map(|g| {
+                assert_eq!(g.len, g.text.chars().count());
+                (g.text, g.start, g.width)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn measures_chars() {
+        assert_eq!(char_width('a'), 1);
+        assert_eq!(char_width('日'), 2);
+        assert_eq!(char_width('\u{FF21}'), 2);
+        assert_eq!(char_width('😀'), 2);
+        assert_eq!(char_width('\u{20000}'), 2);
+        assert_eq!(char_width('\u{301}'), 0);
+        assert_eq!(char_width(ZWJ), 0);
+        assert_eq!(char_width('\t'), 0);
+    }
+
+    #[test]
+    fn joins_combining_characters() {
+        assert_eq!(split("e\u{301}x"), [("e\u{301}", 0, 1), ("x", 2, 1)]);
+        assert_eq!(split("\u{301}a"), [("\u{301}", 0, 0), ("a", 1, 1)]);
+    }
+
+    #[test]
+    fn joins_emoji() {
+        let family = "👩\u{200D}👧";
+        assert_eq!(split(family), [(family, 0, 2)]);
+        // a narrow character shown as an emoji
+        assert_eq!(split("☺\u{FE0F}!"), [("☺\u{FE0F}", 0, 2), ("!", 2, 1)]);
+        // regional indicators pair up into flags
+        assert_eq!(split("🇯🇵🇫"), [("🇯🇵", 0, 2), ("🇫", 2, 1)]);
+    }
+
+    #[test]
+    fn truncates_to_whole_graphemes() {
+        assert_eq!(width("日本語"), 6);
+        assert_eq!(truncate("日本語", 5), "日本");
+        assert_eq!(truncate("日本語", 6), "日本語");
+        assert_eq!(truncate("e\u{301}e\u{301}", 1), "e\u{301}");
+        assert_eq!(truncate("🇯🇵🇯🇵", 3), "🇯🇵");
+        assert_eq!(truncate("ab", 0), "");
+    }
+}
@@ -0,0 +1,452 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{Receiver, Sender, TryRecvError},
+        Arc,
+    },
+};
+
+use rayon::{prelude::*, ThreadPoolBuilder};
+
+use crate::search::{Match, Scoring, SearchKey};
+use crate::waker::Waker;
+use crate::{Matcher, Tiebreak};
+
+// Most queries whose results are kept, and most results kept across all of them, around 15 MB
+// of them. The newest results are always kept.
+const CACHE_QUERIES: usize = 64;
+const CACHE_RESULTS: usize = 1 << 18;
+
+// Most queries searched ahead of being typed, and how many items their next chars are
+// guessed from.
+const PREFETCH_QUERIES: usize = 8;
+const PREFETCH_SAMPLE: usize = 1000;
+
+// Items searched between checks for a newer query and reports of how far a search has got.
+const SEARCH_CHUNK: usize = 50_000;
+
+pub(crate) enum Request {
+    /// Items that arrived, in order.
+    Items(Vec<Arc<SearchKey>>),
+    /// Search for a query, and for the items that arrive after it, until the generation moves
+    /// on.
+    Search(String, usize),
+    /// No more items will arrive. Needs no reply.
+    End,
+}
+
+pub(crate) enum Reply {
+    /// More results for the search of a generation, with how many items of how many it has
+    /// looked at.
+    Found {
+        generation: usize,
+        matches: Arc<Vec<Match>>,
+        searched: usize,
+        total: usize,
+    },
+    /// A request of the generation is done.
+    Finished(usize),
+}
+
+// What results are cached by. The matcher is fixed for the life of a `Worker`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Key {
+    query: String,
+    tiebreaks: Vec<Tiebreak>,
+}
+
+impl Key {
+    fn new(query: &str, tiebreaks: &[Tiebreak]) -> Key {
+        Key {
+            query: query.to_string(),
+            tiebreaks: tiebreaks.to_vec(),
+        }
+    }
+}
+
+/// Searches items on a thread of its own, with results of recent and likely next queries
+/// cached.
+///
+/// A query found in the cache only costs searching the items that arrived since, and one that
+/// narrows a cached query only needs to look at that query's results, so deleting back to an
+/// earlier query or typing a guessed one is instant. A search is abandoned as soon as a newer
+/// one is asked for, and guesses are only searched while there's nothing else to do, once
+/// every item has arrived.
+pub(crate) struct Worker {
+    matcher: Arc<dyn Matcher>,
+    tiebreaks: Vec<Tiebreak>,
+    // the newest generation asked for, which any other search gives way to
+    latest: Arc<AtomicUsize>,
+    requests: Receiver<Request>,
+    // requests taken early, to see whether a search ahead should give way
+    backlog: VecDeque<Request>,
+    replies: Sender<Reply>,
+    waker: Waker,
+    keys: Vec<Arc<SearchKey>>,
+    // whether every item has arrived
+    ended: bool,
+    query: String,
+    generation: usize,
+    // all the matches for the current query once searched, kept apart from the cache so that
+    // items arriving later can be searched and added to them
+    current: Option<Arc<Vec<Match>>>,
+    // least recently used first, and only complete results, each with how many of the keys it
+    // has searched
+    cache: Vec<(Key, Arc<Vec<Match>>, usize)>,
+    // queries to search ahead, the likeliest last
+    pending: Vec<String>,
+}
+
+impl Worker {
+    pub(crate) fn new(
+        matcher: Arc<dyn Matcher>,
+        tiebreaks: &[Tiebreak],
+        latest: Arc<AtomicUsize>,
+        requests: Receiver<Request>,
+        replies: Sender<Reply>,
+        waker: Waker,
+    ) -> Worker {
+        Worker {
+            matcher,
+            tiebreaks: tiebreaks.to_vec(),
+            latest,
+            requests,
+            backlog: VecDeque::new(),
+            replies,
+            waker,
+            keys: Vec::new(),
+            ended: false,
+            query: String::new(),
+            generation: 0,
+            current: None,
+            cache: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Answer requests until whoever sends them is gone.
+    ///
+    /// Searches run on a rayon pool of their own, so ranking results on the global pool never
+    /// waits for them.
+    pub(crate) fn run(mut self) {
+        match ThreadPoolBuilder::new().build() {
+            Ok(pool) => pool.install(|| self.serve()),
+            Err(_) => self.serve(),
+        }
+    }
+
+    fn serve(&mut self) {
+        while let Some(request) = self.next() {
+            match request {
+                Request::Items(keys) => self.add(keys),
+                Request::Search(query, generation) => self.start(query, generation),
+                Request::End => self.end(),
+            }
+        }
+    }
+
+    fn start(&mut self, query: String, generation: usize) {
+        self.query = query;
+        self.generation = generation;
+        self.current = None;
+        let query = self.query.clone();
+        if !query.is_empty() {
+            match self.search(&query, generation, false) {
+                Some(matches) => self.current = Some(matches),
+                None => return,
+            }
+        }
+        if self.ended {
+            self.pending = self.guesses();
+        }
+        self.reply(Reply::Finished(generation));
+    }
+
+    fn end(&mut self) {
+        self.ended = true;
+        self.pending = self.guesses();
+    }
+
+    // The next request, searching ahead until there is one.
+    fn next(&mut self) -> Option<Request> {
+        loop {
+            if let Some(request) = self.backlog.pop_front() {
+                return Some(request);
+            }
+            match self.requests.try_recv() {
+                Ok(request) => return Some(request),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+            let query = match self.pending.pop() {
+                Some(query) => query,
+                None => return self.requests.recv().ok(),
+            };
+            let generation = self.generation;
+            if self.search(&query, generation, true).is_none() && !self.cancelled(generation) {
+                // try again once the request it gave way to is done
+                self.pending.push(query);
+            }
+        }
+    }
+
+    // Whether a request is waiting, which searching ahead gives way to.
+    fn waiting(&mut self) -> bool {
+        if self.backlog.is_empty() {
+            if let Ok(request) = self.requests.try_recv() {
+                self.backlog.push_back(request);
+            }
+        }
+        !self.backlog.is_empty()
+    }
+
+    fn cancelled(&self, generation: usize) -> bool {
+        self.latest.load(Ordering::Relaxed) != generation
+    }
+
+    fn reply(&self, reply: Reply) {
+        // nobody is left to tell once the picker has closed
+        let _ = self.replies.send(reply);
+        self.waker.wake();
+    }
+
+    fn add(&mut self, keys: Vec<Arc<SearchKey>>) {
+        let start = self.keys.len();
+        self.keys.extend(keys);
+        if self.cancelled(self.generation) {
+            // the newer search looks at these too
+            return;
+        }
+
+        if let Some(current) = self.current.take() {
+            let found = self.search_from(&self.query, start);
+            let mut matches = Arc::try_unwrap(current).unwrap_or_else(|c| (*c).clone());
+            matches.extend(&found);
+            let matches = Arc::new(matches);
+            self.current = Some(matches.clone());
+            self.insert(Key::new(&self.query, &self.tiebreaks), matches);
+            self.reply(Reply::Found {
+                generation: self.generation,
+                matches: Arc::new(found),
+                searched: self.keys.len() - start,
+                total: self.keys.len() - start,
+            });
+        }
+        self.reply(Reply::Finished(self.generation));
+    }
+
+    // Matches for `query` among the keys from `start` on.
+    fn search_from(&self, query: &str, start: usize) -> Vec<Match> {
+        let scoring = Scoring::new(&*self.matcher, query, &self.tiebreaks);
+        self.keys[start..]
+            .par_iter()
+            .enumerate()
+            .filter_map(|(i, key)| scoring.score(start + i, key))
+            .collect()
+    }
+
+    // Matches for `query`, cached or searched from the longest cached query it narrows, or
+    // `None` if a newer search was asked for first. Matches are sent back as they're found,
+    // unless searching `ahead`, which also gives way to any other request.
+    fn search(&mut self, query: &str, generation: usize, ahead: bool) -> Option<Arc<Vec<Match>>> {
+        let key = Key::new(query, &self.tiebreaks);
+        if let Some(matches) = self.get(&key) {
+            if !ahead {
+                self.reply(Reply::Found {
+                    generation,
+                    matches: matches.clone(),
+                    searched: self.keys.len(),
+                    total: self.keys.len(),
+                });
+            }
+            return Some(matches);
+        }
+
+        let matcher = self.matcher.clone();
+        let narrowed = query
+            .char_indices()
+            .rev()
+            .map(|(i, _)| &query[..i])
+            .filter(|previous| !previous.is_empty() && matcher.narrows(previous, query))
+            .find_map(|previous| self.get(&Key::new(previous, &self.tiebreaks)));
+        let scoring = Scoring::new(&*matcher, query, &self.tiebreaks);
+        let total = narrowed.as_ref().map_or(self.keys.len(), |n| n.len());
+        let mut matches = Vec::new();
+        for start in (0..total).step_by(SEARCH_CHUNK) {
+            if self.cancelled(generation) || ahead && self.waiting() {
+                return None;
+            }
+            let end = (start + SEARCH_CHUNK).min(total);
+            let keys = &self.keys;
+            let found: Vec<_> = match &narrowed {
+                Some(narrowed) => narrowed[start..end]
+                    .par_iter()
+                    .filter_map(|m| scoring.score(m.index, &keys[m.index]))
+                    .collect(),
+                None => keys[start..end]
+                    .par_iter()
+                    .enumerate()
+                    .filter_map(|(i, key)| scoring.score(start + i, key))
+                    .collect(),
+            };
+            matches.extend(&found);
+            if !ahead {
+                self.reply(Reply::Found {
+                    generation,
+                    matches: Arc::new(found),
+                    searched: end,
+                    total,
+                });
+            }
+        }
+        if !ahead && total == 0 {
+            self.reply(Reply::Found {
+                generation,
+                matches: Arc::default(),
+                searched: 0,
+                total,
+            });
+        }
+
+        let matches = Arc::new(matches);
+        self.insert(key, matches.clone());
+        Some(matches)
+    }
+
+    fn get(&mut self, key: &Key) -> Option<Arc<Vec<Match>>> {
+        let i = self.cache.iter().position(|(k, _, _)| k == key)?;
+        let (key, mut matches, searched) = self.cache.remove(i);
+        if searched < self.keys.len() {
+            // items arrived since it was searched
+            let found = self.search_from(&key.query, searched);
+            Arc::make_mut(&mut matches).extend(found);
+        }
+        self.cache.push((key, matches.clone(), self.keys.len()));
+        Some(matches)
+    }
+
+    fn insert(&mut self, key: Key, matches: Arc<Vec<Match>>) {
+        self.cache.retain(|(k, _, _)| *k != key);
+        self.cache.push((key, matches, self.keys.len()));
+        let mut total: usize = self.cache.iter().map(|(_, m, _)| m.len()).sum();
+        while self.cache.len() > 1 && (self.cache.len() > CACHE_QUERIES || total > CACHE_RESULTS) {
+            let (_, evicted, _) = self.cache.remove(0);
+            total -= evicted.len();
+        }
+    }
+
+    // The current query without its last char, in case it's deleted, then the current query
+    // followed by each of the chars most common in its results.
+    fn guesses(&self) -> Vec<String> {
+        let sample: Vec<_> = if self.query.is_empty() {
+            self.keys.iter().take(PREFETCH_SAMPLE).collect()
+        } else {
+            let matches = self.current.clone().unwrap_or_default();
+            let matches = matches.iter().take(PREFETCH_SAMPLE);
+            matches.map(|m| &self.keys[m.index]).collect()
+        };
+        let mut counts: HashMap<char, usize> = HashMap::new();
+        for key in sample {
+            for c in key.text.chars().filter(|c| c.is_alphanumeric()) {
+                *counts.entry(c).or_default() += 1;
+            }
+        }
+        let mut chars: Vec<_> = counts.into_iter().collect();
+        chars.sort_by(|(a, m), (b, n)| n.cmp(m).then(a.cmp(b)));
+
+        let mut guesses: Vec<_> = chars
+            .into_iter()
+            .take(PREFETCH_QUERIES)
+            .map(|(c, _)| format!("{}{}", self.query, c))
+            .rev()
+            .collect();
+        let mut shorter = self.query.clone();
+        shorter.pop();
+        if !shorter.is_empty() {
+            guesses.push(shorter);
+        }
+        guesses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+    use crate::matcher::Exact;
+    use crate::CaseMatching;
+
+    fn keys(items: &[&str]) -> Vec<Arc<SearchKey>> {
+        let items = items.iter().map(|s| s.to_string());
+        items.map(|item| Arc::new(SearchKey::new(&item))).collect()
+    }
+
+    #[test]
+    fn searches_new_items_after_the_cache_is_full() {
+        let (_, requests) = channel();
+        let (replies, found) = channel();
+        let matcher = Arc::new(Exact::new(CaseMatching::Smart));
+        let latest = Arc::new(AtomicUsize::new(1));
+        let mut worker = Worker::new(matcher, &[], latest, requests, replies, Waker::default());
+        worker.add(keys(&["cat", "dog"]));
+        worker.start("a".to_string(), 1);
+        // searching ahead fills the cache, pushing the current query out
+        for i in 0..=CACHE_QUERIES {
+            let matches = Arc::new(vec![]);
+            worker.insert(Key::new(&format!("x{}", i), &[]), matches);
+        }
+        worker.add(keys(&["bat", "cow"]));
+
+        let mut indices = Vec::new();
+        for reply in found.try_iter() {
+            if let Reply::Found { matches, .. } = reply {
+                indices.extend(matches.iter().map(|m| m.index));
+            }
+        }
+        assert_eq!(indices, [0, 2]);
+    }
+
+    #[test]
+    fn extends_cached_results_with_new_items_and_guesses_once_they_end() {
+        let (_, requests) = channel();
+        let (replies, found) = channel();
+        let matcher = Arc::new(Exact::new(CaseMatching::Smart));
+        let latest = Arc::new(AtomicUsize::new(1));
+        let mut worker = Worker::new(
+            matcher,
+            &[],
+            latest.clone(),
+            requests,
+            replies,
+            Waker::default(),
+        );
+        worker.add(keys(&["cat", "dog"]));
+        worker.start("a".to_string(), 1);
+        latest.store(2, Ordering::Relaxed);
+        worker.start("o".to_string(), 2);
+        worker.add(keys(&["bat", "cow"]));
+        assert_eq!(worker.cache.len(), 2);
+        assert!(worker.pending.is_empty());
+
+        latest.store(3, Ordering::Relaxed);
+        worker.start("a".to_string(), 3);
+        let mut indices = Vec::new();
+        for reply in found.try_iter() {
+            if let Reply::Found {
+                generation: 3,
+                matches,
+                ..
+            } = reply
+            {
+                indices.extend(matches.iter().map(|m| m.index));
+            }
+        }
+        assert_eq!(indices, [0, 2]);
+        assert!(worker.pending.is_empty());
+
+        worker.end();
+        assert!(!worker.pending.is_empty());
+    }
+}
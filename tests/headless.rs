@@ -0,0 +1,1213 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc::Sender, Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossterm::event::Event;
+
+use picky::{
+    Action, Algorithm, Attribute, Backend, Color, ContentStyle, Delimiter, Events, Fields, Item,
+    KeyCode, KeyEvent, KeyModifiers, Matcher, Outcome, Picker, Plain, PreviewPosition, PreviewSize,
+    ScriptedEvents, Source, StyledContent, Terminal, TestBackend, Theme, Tiebreak,
+};
+
+const ANIMALS: &[&str] = &["dogs", "cats", "mice", "bears", "sheep", "goats", "ducks"];
+
+fn items(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+fn picker() -> Picker<String> {
+    Picker::new().colors(false)
+}
+
+// Run the picker over `list` on a 30x10 screen, returning the result and what was on screen
+// just before the picker closed and cleared itself.
+fn run(
+    picker: &Picker<String>,
+    list: &[&str],
+    events: ScriptedEvents,
+    multi: bool,
+) -> (Option<Vec<String>>, Vec<String>) {
+    let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+    let result = picker.select_on(&mut terminal, items(list), multi).unwrap();
+    assert!(terminal.events_mut().is_empty(), "picker closed early");
+    assert!(!terminal.backend().is_raw());
+
+    let frames = terminal.backend().frames();
+    (result, frames[frames.len() - 2].clone())
+}
+
+fn screen(lines: &[&str]) -> Vec<String> {
+    let mut screen = items(lines);
+    screen.resize(10, String::new());
+    screen
+}
+
+#[test]
+fn shows_items_in_input_order() {
+    let events = ScriptedEvents::new().idle().key(KeyCode::Esc);
+    let (result, drawn) = run(&picker(), ANIMALS, events, false);
+    assert_eq!(result, None);
+    assert_eq!(
+        drawn,
+        screen(&[">", "1> dogs", "2: cats", "3: mice", "4: bears", "5: sheep"])
+    );
+}
+
+#[test]
+fn filters_by_query() {
+    let events = ScriptedEvents::new().text("ts").key(KeyCode::Esc);
+    let (_, drawn) = run(&picker(), ANIMALS, events, false);
+    assert_eq!(drawn, screen(&["> ts", "1> cats", "2: goats"]));
+}
+
+#[test]
+fn accepts_the_highlighted_item() {
+    let events = ScriptedEvents::new()
+        .key(KeyCode::Down)
+        .key(KeyCode::Down)
+        .key(KeyCode::Enter);
+    let (result, drawn) = run(&picker(), ANIMALS, events, false);
+    assert_eq!(result, Some(items(&["mice"])));
+    assert_eq!(
+        drawn,
+        screen(&[">", "1: dogs", "2: cats", "3> mice", "4: bears", "5: sheep"])
+    );
+}
+
+#[test]
+fn aborts_with_ctrl_c() {
+    let events = ScriptedEvents::new().key_with(KeyCode::Char('c'), KeyModifiers::CONTROL);
+    let (result, _) = run(&picker(), ANIMALS, events, false);
+    assert_eq!(result, None);
+}
+
+#[test]
+fn scrolls_past_the_visible_rows() {
+    let events = ScriptedEvents::new()
+        .key_with(KeyCode::End, KeyModifiers::CONTROL)
+        .key(KeyCode::Up)
+        .key(KeyCode::Esc);
+    let (_, drawn) = run(&picker(), ANIMALS, events, false);
+    assert_eq!(
+        drawn,
+        screen(&[">", "3: mice", "4: bears", "5: sheep", "6> goats", "7: ducks"])
+    );
+}
+
+#[test]
+fn home_and_end_jump_once_the_query_cursor_is_there() {
+    let accept = |events: ScriptedEvents| {
+        let (result, _) = run(&picker(), ANIMALS, events.key(KeyCode::Enter), false);
+        result
+    };
+    let typed = || ScriptedEvents::new().text("s");
+    let first = accept(typed());
+    let last = accept(typed().key_with(KeyCode::End, KeyModifiers::CONTROL));
+    assert_ne!(first, last);
+
+    assert_eq!(accept(typed().key(KeyCode::End)), last);
+    assert_eq!(accept(typed().key(KeyCode::Home).key(KeyCode::End)), first);
+    let home = typed().key(KeyCode::End).key(KeyCode::Home);
+    assert_eq!(accept(home.clone()), last);
+    assert_eq!(accept(home.key(KeyCode::Home)), first);
+}
+
+#[test]
+fn marks_no_more_than_max_selections() {
+    let picker = picker().max_selections(2);
+    let runs = [
+        (
+            ScriptedEvents::new()
+                .key(KeyCode::Tab)
+                .key(KeyCode::Tab)
+                .key(KeyCode::Tab),
+            ["dogs", "cats"],
+        ),
+        (
+            ScriptedEvents::new().key_with(KeyCode::Char('a'), KeyModifiers::ALT),
+            ["dogs", "cats"],
+        ),
+        (
+            ScriptedEvents::new()
+                .key(KeyCode::Tab)
+                .key_with(KeyCode::Char('t'), KeyModifiers::ALT),
+            ["cats", "mice"],
+        ),
+    ];
+    for (events, marked) in runs {
+        let (result, _) = run(&picker, ANIMALS, events.key(KeyCode::Enter), true);
+        assert_eq!(result, Some(items(&marked)));
+    }
+}
+
+#[test]
+fn marks_items_in_multi_mode() {
+    let events = ScriptedEvents::new()
+        .key(KeyCode::Tab)
+        .key(KeyCode::Down)
+        .key(KeyCode::Tab)
+        .key(KeyCode::Enter);
+    let (result, drawn) = run(&picker(), ANIMALS, events, true);
+    assert_eq!(result, Some(items(&["dogs", "mice"])));
+    assert_eq!(
+        drawn,
+        screen(&[
+            ">",
+            "*1: dogs",
+            " 2: cats",
+            "*3: mice",
+            " 4> bears",
+            " 5: sheep"
+        ])
+    );
+}
+
+#[test]
+fn draws_header_and_prompt() {
+    let picker = picker().prompt("animal: ").header("pick one").height(2);
+    let events = ScriptedEvents::new().text("d").key(KeyCode::Esc);
+    let (_, drawn) = run(&picker, ANIMALS, events, false);
+    assert_eq!(
+        drawn,
+        screen(&["animal: d", "   pick one", "1> dogs", "2: ducks"])
+    );
+}
+
+// A backend the test can look at while the picker draws on it.
+#[derive(Clone)]
+struct Shared(Arc<Mutex<TestBackend>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
+
+impl Backend for Shared {
+    fn enable_raw_mode(&mut self) -> crossterm::Result<()> {
+        self.0.lock().unwrap().enable_raw_mode()
+    }
+
+    fn disable_raw_mode(&mut self) -> crossterm::Result<()> {
+        self.0.lock().unwrap().disable_raw_mode()
+    }
+
+    fn size(&self) -> crossterm::Result<(u16, u16)> {
+        self.0.lock().unwrap().size()
+    }
+
+    fn position(&mut self) -> crossterm::Result<(u16, u16)> {
+        self.0.lock().unwrap().position()
+    }
+}
+
+// Scripted events that look at the screen when the picker waits for a key after the last one.
+struct Watching {
+    events: ScriptedEvents,
+    backend: Shared,
+    seen: Option<(String, (u16, u16))>,
+}
+
+impl Events for Watching {
+    fn poll(&mut self, timeout: Duration) -> crossterm::Result<bool> {
+        if self.events.is_empty() && self.seen.is_none() {
+            let backend = self.backend.0.lock().unwrap();
+            self.seen = Some((backend.lines()[0].clone(), backend.cursor()));
+        }
+        self.events.poll(timeout)
+    }
+
+    fn poll_busy(&mut self, timeout: Duration) -> crossterm::Result<bool> {
+        self.events.poll_busy(timeout)
+    }
+
+    fn read(&mut self) -> crossterm::Result<Event> {
+        self.events.read()
+    }
+}
+
+// The query line and where the cursor is once the picker has dealt with `events`.
+fn query_line(events: ScriptedEvents) -> (String, (u16, u16)) {
+    let backend = Shared(Arc::new(Mutex::new(TestBackend::new(30, 10))));
+    let events = Watching {
+        events,
+        backend: backend.clone(),
+        seen: None,
+    };
+    let mut terminal = Terminal::new(backend, events);
+    // running out of events closes the picker
+    assert!(picker()
+        .select_on(&mut terminal, items(ANIMALS), false)
+        .is_err());
+    terminal.events_mut().seen.take().unwrap()
+}
+
+#[test]
+fn places_the_cursor_in_the_query() {
+    let cursor = |keys: &[KeyCode]| {
+        let events = ScriptedEvents::new().text("日本");
+        let events = keys.iter().fold(events, |events, &key| events.key(key));
+        let (line, cursor) = query_line(events);
+        assert_eq!(line, "> 日本");
+        cursor
+    };
+    assert_eq!(cursor(&[KeyCode::Left]), (4, 0));
+    // Home and End move in the query, not through the results
+    assert_eq!(cursor(&[KeyCode::Home]), (2, 0));
+    assert_eq!(cursor(&[KeyCode::Home, KeyCode::End]), (6, 0));
+}
+
+#[test]
+fn edits_combined_characters_as_one() {
+    let events = ScriptedEvents::new()
+        .text("cafe\u{301}s")
+        .key(KeyCode::Left)
+        .key(KeyCode::Backspace);
+    assert_eq!(query_line(events), ("> cafs".to_string(), (5, 0)));
+}
+
+#[test]
+fn puts_the_terminal_back_when_the_picker_fails() {
+    // running out of events is an error
+    let events = ScriptedEvents::new().text("d");
+    let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+    assert!(picker()
+        .select_on(&mut terminal, items(ANIMALS), false)
+        .is_err());
+    let backend = terminal.backend();
+    assert!(!backend.is_raw());
+    assert_eq!(backend.lines(), screen(&[]));
+    assert!(backend.output().ends_with("\x1b[?1000l"));
+}
+
+// An item that can't be drawn.
+#[derive(Clone)]
+struct Broken;
+
+impl Item for Broken {
+    fn search_text(&self) -> Cow<'_, str> {
+        "broken".into()
+    }
+
+    fn display(&self) -> Vec<StyledContent<String>> {
+        panic!("can't draw")
+    }
+}
+
+#[test]
+fn puts_the_terminal_back_when_the_picker_panics() {
+    let events = ScriptedEvents::new().key(KeyCode::Esc);
+    let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+    let picked = panic::catch_unwind(AssertUnwindSafe(|| {
+        Picker::new().select_on(&mut terminal, vec![Broken], false)
+    }));
+    assert!(picked.is_err());
+    assert!(!terminal.backend().is_raw());
+    assert_eq!(terminal.backend().lines(), screen(&[]));
+}
+
+#[test]
+fn truncates_wide_characters_to_the_screen() {
+    let list = [
+        "日本語の文章はとても長いです",
+        "plain ascii that is far too long",
+    ];
+    let events = ScriptedEvents::new().idle().key(KeyCode::Esc);
+    let (_, drawn) = run(&picker().height(2), &list, events, false);
+    assert_eq!(
+        drawn,
+        screen(&[
+            ">",
+            "1> 日本語の文章はとても長いで",
+            "2: plain ascii that is far too",
+        ])
+    );
+}
+
+#[test]
+fn shows_a_preview_of_the_highlighted_item() {
+    let picker = picker()
+        .height(3)
+        .preview(|item: &String| format!("about {}\nline 2", item))
+        .preview_window(PreviewPosition::Bottom, PreviewSize::Fixed(2));
+    let events = ScriptedEvents::new().key(KeyCode::Down).key(KeyCode::Esc);
+    let (_, drawn) = run(&picker, ANIMALS, events, false);
+    assert_eq!(
+        drawn,
+        screen(&[
+            ">",
+            "1: dogs",
+            "2> cats",
+            "3: mice",
+            "──────────────────────────────",
+            "about cats",
+            "line 2",
+        ])
+    );
+}
+
+#[test]
+fn shows_previews_that_panic_as_errors() {
+    let picker = picker()
+        .height(3)
+        .preview(|item: &String| match item.as_str() {
+            "dogs" => panic!("no dogs"),
+            item => format!("about {}", item),
+        })
+        .preview_window(PreviewPosition::Bottom, PreviewSize::Fixed(1));
+    let events = ScriptedEvents::new().key(KeyCode::Esc);
+    let (_, drawn) = run(&picker, ANIMALS, events, false);
+    let separator = "─".repeat(30);
+    let failed = "preview failed: no dogs";
+    let shown = [">", "1> dogs", "2: cats", "3: mice", &separator, failed];
+    assert_eq!(drawn, screen(&shown));
+
+    // and later previews are still made
+    let events = ScriptedEvents::new().key(KeyCode::Down).key(KeyCode::Esc);
+    let (_, drawn) = run(&picker, ANIMALS, events, false);
+    let shown = [
+        ">",
+        "1: dogs",
+        "2> cats",
+        "3: mice",
+        &separator,
+        "about cats",
+    ];
+    assert_eq!(drawn, screen(&shown));
+}
+
+#[test]
+fn hidden_previews_leave_their_room_to_the_results() {
+    let toggle = || {
+        let alt = KeyModifiers::ALT;
+        let events = ScriptedEvents::new().key_with(KeyCode::Char('p'), alt);
+        events.key(KeyCode::Esc)
+    };
+    let preview = |item: &String| format!("about {}", item);
+    let beside = picker()
+        .height(3)
+        .preview(preview)
+        .preview_window(PreviewPosition::Right, PreviewSize::Fixed(10));
+    let (_, drawn) = run(
+        &beside,
+        &["a mouse of some length", "a dog"],
+        toggle(),
+        false,
+    );
+    assert_eq!(
+        drawn,
+        screen(&[">", "1> a mouse of some length", "2: a dog"])
+    );
+
+    let below = picker()
+        .height(3)
+        .preview(preview)
+        .preview_window(PreviewPosition::Bottom, PreviewSize::Fixed(2));
+    let (_, drawn) = run(&below, ANIMALS, toggle(), false);
+    assert_eq!(
+        drawn,
+        screen(&[">", "1> dogs", "2: cats", "3: mice", "4: bears", "5: sheep", "6: goats"])
+    );
+}
+
+#[test]
+fn fits_a_resized_terminal() {
+    let events = ScriptedEvents::new()
+        .event(Event::Resize(12, 3))
+        .key(KeyCode::Esc);
+    let list = &["a rather long line", "dogs", "cats", "mice"];
+    let (_, drawn) = run(&picker().height(3), list, events, false);
+    assert_eq!(drawn, screen(&[">", "1> a rather", "2: dogs"]));
+}
+
+#[test]
+fn clicks_select_and_double_clicks_accept() {
+    let events = ScriptedEvents::new().click(4, 4).click(4, 4);
+    let (result, _) = run(&picker(), ANIMALS, events, false);
+    assert_eq!(result, Some(items(&["bears"])));
+}
+
+#[test]
+fn readline_editing() {
+    let events = ScriptedEvents::new()
+        .text("sheep goats")
+        .key_with(KeyCode::Char('w'), KeyModifiers::CONTROL)
+        .key_with(KeyCode::Char('a'), KeyModifiers::CONTROL)
+        .key_with(KeyCode::Char('y'), KeyModifiers::CONTROL)
+        .key_with(KeyCode::Char('k'), KeyModifiers::CONTROL)
+        .key(KeyCode::Esc);
+    let (_, drawn) = run(&picker(), ANIMALS, events, false);
+    assert_eq!(drawn, screen(&["> goats", "1> goats"]));
+}
+
+fn outcome(picker: &Picker<String>, events: ScriptedEvents, multi: bool) -> Outcome<String> {
+    let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+    let outcome = picker
+        .outcome_on(&mut terminal, items(ANIMALS), multi)
+        .unwrap();
+    assert!(terminal.events_mut().is_empty(), "picker closed early");
+    outcome
+}
+
+#[test]
+fn outcome_tells_how_the_picker_closed() {
+    let events = ScriptedEvents::new().text("s").key(KeyCode::Esc);
+    let closed = outcome(&picker(), events, false);
+    assert_eq!(closed.action, Action::Abort);
+    assert!(closed.closed_with(KeyEvent::new(KeyCode::Esc, KeyModifiers::empty())));
+    assert_eq!(closed.query, "s");
+    assert!(closed.items.is_empty());
+
+    let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+    let closed = outcome(
+        &picker(),
+        ScriptedEvents::new().key_with(ctrl_c.code, ctrl_c.modifiers),
+        false,
+    );
+    assert!(!closed.is_accepted());
+    assert!(closed.closed_with(ctrl_c));
+}
+
+#[test]
+fn outcome_has_items_and_their_indices() {
+    let events = ScriptedEvents::new()
+        .key_with(KeyCode::End, KeyModifiers::CONTROL)
+        .key(KeyCode::Tab)
+        .key_with(KeyCode::Home, KeyModifiers::CONTROL)
+        .key(KeyCode::Tab)
+        .key(KeyCode::Enter);
+    let accepted = outcome(&picker(), events, true);
+    assert!(accepted.is_accepted());
+    assert_eq!(accepted.items, items(&["dogs", "ducks"]));
+    assert_eq!(accepted.indices, vec![0, 6]);
+
+    let events = ScriptedEvents::new().text("xyz").key(KeyCode::Enter);
+    let accepted = outcome(&picker(), events, false);
+    assert!(accepted.is_accepted());
+    assert!(accepted.items.is_empty());
+    assert_eq!(accepted.selected(), Some(vec![]));
+}
+
+#[test]
+fn expected_keys_accept() {
+    let open = KeyEvent::new(KeyCode::Char('o'), KeyModifiers::CONTROL);
+    let events = ScriptedEvents::new()
+        .key(KeyCode::Down)
+        .key_with(open.code, open.modifiers);
+    let accepted = outcome(&picker().expect(open), events, false);
+    assert!(accepted.is_accepted());
+    assert!(accepted.closed_with(open));
+    assert_eq!(accepted.items, items(&["cats"]));
+    assert_eq!(accepted.indices, vec![1]);
+}
+
+#[test]
+fn double_clicks_accept_without_a_key() {
+    let events = ScriptedEvents::new().click(4, 2).click(4, 2);
+    let accepted = outcome(&picker(), events, false);
+    assert!(accepted.is_accepted());
+    assert_eq!(accepted.key, None);
+    assert_eq!(accepted.items, items(&["cats"]));
+}
+
+const PROCESSES: &[&str] = &[
+    "root   1  init  --system",
+    "alice  42  vim  notes-root.txt",
+    "bob  7  sh",
+];
+
+#[test]
+fn matches_only_the_chosen_fields() {
+    let picker = picker().nth("1".parse().unwrap());
+    let events = ScriptedEvents::new().text("root").key(KeyCode::Enter);
+    let (result, drawn) = run(&picker, PROCESSES, events, false);
+    assert_eq!(result, Some(items(&["root   1  init  --system"])));
+    assert_eq!(drawn, screen(&["> root", "1> root   1  init  --system"]));
+
+    let picker = picker.nth("-1".parse().unwrap());
+    let events = ScriptedEvents::new().text("root").key(KeyCode::Enter);
+    let (result, _) = run(&picker, PROCESSES, events, false);
+    assert_eq!(result, Some(items(&["alice  42  vim  notes-root.txt"])));
+}
+
+#[test]
+fn shows_only_the_chosen_fields() {
+    let picker = picker()
+        .delimiter(Delimiter::pattern("  ").unwrap())
+        .with_nth("1,3".parse().unwrap())
+        .nth("2".parse().unwrap());
+    let events = ScriptedEvents::new().text("s").key(KeyCode::Esc);
+    let (_, drawn) = run(&picker, PROCESSES, events, false);
+    assert_eq!(drawn, screen(&["> s", "1> bob  sh"]));
+
+    // the whole item is returned
+    let events = ScriptedEvents::new().text("v").key(KeyCode::Enter);
+    let (result, drawn) = run(&picker, PROCESSES, events, false);
+    assert_eq!(result, Some(items(&["alice  42  vim  notes-root.txt"])));
+    assert_eq!(drawn, screen(&["> v", "1> alice  vim"]));
+}
+
+#[test]
+fn anchored_delimiters_split_only_at_the_start() {
+    let picker = picker()
+        .delimiter(Delimiter::pattern("^x").unwrap())
+        .with_nth("2".parse().unwrap());
+    let events = ScriptedEvents::new().key(KeyCode::Esc);
+    let (_, drawn) = run(&picker, &["xaxbx"], events, false);
+    assert_eq!(drawn, screen(&[">", "1> axbx"]));
+}
+
+#[test]
+fn parses_field_index_expressions() {
+    for valid in &["1", "-1", "2..", "..3", "2..4", "1,3..", "-2..-1"] {
+        assert!(valid.parse::<Fields>().is_ok(), "{}", valid);
+    }
+    for invalid in &["", "0", "a", "1..b", "1,,2"] {
+        assert!(invalid.parse::<Fields>().is_err(), "{}", invalid);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Process {
+    pid: u32,
+    name: &'static str,
+}
+
+impl Item for Process {
+    fn search_text(&self) -> Cow<'_, str> {
+        format!("{} {}", self.name, self.pid).into()
+    }
+
+    fn display(&self) -> Vec<StyledContent<String>> {
+        vec![
+            ContentStyle::new().apply(format!("{:>5}", self.pid)),
+            ContentStyle::new().apply(format!(" {:<6}|", self.name)),
+        ]
+    }
+
+    fn preview(&self) -> Option<String> {
+        Some(format!("pid {}", self.pid))
+    }
+}
+
+// Which `Item` doesn't use, nor stop it from drawing processes its own way.
+impl fmt::Display for Process {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.pid)
+    }
+}
+
+#[test]
+fn items_search_and_draw_different_text() {
+    let processes = vec![
+        Process {
+            pid: 1,
+            name: "init",
+        },
+        Process {
+            pid: 42,
+            name: "vim",
+        },
+        Process {
+            pid: 420,
+            name: "sh",
+        },
+    ];
+    let picker = Picker::new()
+        .colors(false)
+        .height(3)
+        .item_preview()
+        .preview_window(PreviewPosition::Bottom, PreviewSize::Fixed(2));
+    let events = ScriptedEvents::new().text("42").key(KeyCode::Enter);
+    let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+    let result = picker
+        .select_on(&mut terminal, processes.clone(), false)
+        .unwrap();
+    assert_eq!(result, Some(vec![processes[1].clone()]));
+
+    let frames = terminal.backend().frames();
+    assert_eq!(
+        frames[frames.len() - 2],
+        screen(&[
+            "> 42",
+            "1>    42 vim   |",
+            "2:   420 sh    |",
+            "",
+            "──────────────────────────────",
+            "pid 42",
+        ])
+    );
+}
+
+#[test]
+fn plain_items_are_drawn_as_their_display() {
+    let processes = vec![
+        Plain(Process {
+            pid: 1,
+            name: "init",
+        }),
+        Plain(Process {
+            pid: 42,
+            name: "vim",
+        }),
+    ];
+    let events = ScriptedEvents::new().text("(4").key(KeyCode::Enter);
+    let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+    let result = Picker::new()
+        .colors(false)
+        .select_on(&mut terminal, processes.clone(), false)
+        .unwrap();
+    assert_eq!(result, Some(vec![processes[1].clone()]));
+    let frames = terminal.backend().frames();
+    assert_eq!(frames[frames.len() - 2][1], "1> vim (42)");
+}
+
+// A fruit drawn in red italics.
+#[derive(Clone)]
+struct Red(&'static str);
+
+impl Item for Red {
+    fn search_text(&self) -> Cow<'_, str> {
+        self.0.into()
+    }
+
+    fn display(&self) -> Vec<StyledContent<String>> {
+        let red = ContentStyle::new().foreground(Color::Red);
+        vec![red.attribute(Attribute::Italic).apply(self.0.to_string())]
+    }
+}
+
+#[test]
+fn draws_item_styles_only_in_color() {
+    let draw = |picker: Picker<Red>| {
+        let events = ScriptedEvents::new().key(KeyCode::Esc);
+        let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+        let fruit = vec![Red("apple"), Red("cherry")];
+        picker.select_on(&mut terminal, fruit, false).unwrap();
+        terminal.backend().output()
+    };
+    let (red, italic) = ("\x1b[38;5;9m", "\x1b[3m");
+    let colored = draw(Picker::new().colors(true).theme(Theme::dark()));
+    assert!(colored.contains(red) && colored.contains(italic));
+
+    let plain = [
+        Picker::new().colors(false).theme(Theme::dark()),
+        Picker::new().colors(true).theme(Theme::monochrome()),
+    ];
+    for picker in plain {
+        let drawn = draw(picker);
+        assert!(!drawn.contains("38;") && !drawn.contains("48;"));
+        assert!(!drawn.contains(italic));
+    }
+}
+
+#[test]
+fn draws_each_part_in_the_theme() {
+    let theme = Theme {
+        prompt: ContentStyle::new().foreground(Color::Magenta),
+        header: ContentStyle::new().foreground(Color::Green),
+        matched: ContentStyle::new().foreground(Color::Yellow),
+        ..Theme::dark()
+    };
+    let draw = |picker: Picker<String>| {
+        let events = ScriptedEvents::new().text("c").key(KeyCode::Esc);
+        let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+        let picker = picker.header("animals").theme(theme.clone());
+        picker
+            .select_on(&mut terminal, items(ANIMALS), false)
+            .unwrap();
+        terminal.backend().output()
+    };
+    let (magenta, green, yellow) = ("\x1b[38;5;13m>", "\x1b[38;5;10manimals", "\x1b[38;5;11mc");
+    let colored = draw(Picker::new().colors(true));
+    assert!(colored.contains(magenta) && colored.contains(green) && colored.contains(yellow));
+
+    // without colors the theme is ignored for the monochrome one
+    let plain = draw(Picker::new().colors(false));
+    assert!(!plain.contains("38;") && !plain.contains("48;"));
+    assert!(plain.contains("\x1b[1m>") && plain.contains("\x1b[4mc"));
+}
+
+// Matches items containing the query, all with the same score.
+struct Contains;
+
+impl Matcher for Contains {
+    fn match_indices(&self, choice: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+        let start = choice.find(query)?;
+        let start = choice[..start].chars().count();
+        Some((0, (start..start + query.chars().count()).collect()))
+    }
+}
+
+fn ranked(tiebreaks: Vec<Tiebreak>) -> Vec<String> {
+    let list = ["xxab", "ab", "xabxxxx", "abxx", "xab"];
+    let picker = picker()
+        .matcher(Contains)
+        .extended(false)
+        .tiebreak(tiebreaks);
+    let events = ScriptedEvents::new().text("ab").key(KeyCode::Esc);
+    let (_, drawn) = run(&picker, &list, events, false);
+    drawn[1..6]
+        .iter()
+        .map(|line| line[3..].to_string())
+        .collect()
+}
+
+#[test]
+fn breaks_ties_in_order() {
+    assert_eq!(
+        ranked(vec![]),
+        items(&["xxab", "ab", "xabxxxx", "abxx", "xab"])
+    );
+    assert_eq!(
+        ranked(vec![Tiebreak::Length]),
+        items(&["ab", "xab", "xxab", "abxx", "xabxxxx"])
+    );
+    assert_eq!(
+        ranked(vec![Tiebreak::Begin, Tiebreak::Length]),
+        items(&["ab", "abxx", "xab", "xabxxxx", "xxab"])
+    );
+    assert_eq!(
+        ranked(vec![Tiebreak::End, Tiebreak::Index]),
+        items(&["ab", "abxx", "xabxxxx", "xab", "xxab"])
+    );
+    assert_eq!(
+        Tiebreak::parse_chain("begin,length"),
+        Ok(vec![Tiebreak::Begin, Tiebreak::Length])
+    );
+    assert!(Tiebreak::parse_chain("shortest").is_err());
+}
+
+// Matches like `Contains`, noting each item it was asked to score and for which query.
+#[derive(Clone, Default)]
+struct Recording {
+    scored: Arc<Mutex<Vec<(String, String)>>>,
+    // whether to say items need every char of the query
+    prefilter: bool,
+}
+
+impl Matcher for Recording {
+    fn match_indices(&self, choice: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+        Contains.match_indices(choice, query)
+    }
+
+    fn score(&self, choice: &str, query: &str) -> Option<i64> {
+        let mut scored = self.scored.lock().unwrap();
+        scored.push((query.to_string(), choice.to_string()));
+        Contains.score(choice, query)
+    }
+
+    fn narrows(&self, previous: &str, query: &str) -> bool {
+        query.starts_with(previous)
+    }
+
+    fn required(&self, query: &str) -> String {
+        if self.prefilter {
+            query.to_string()
+        } else {
+            String::new()
+        }
+    }
+}
+
+impl Recording {
+    // Items scored against `query`, sorted since they're scored in parallel.
+    fn choices(&self, query: &str) -> Vec<String> {
+        let scored = self.scored.lock().unwrap();
+        let mut choices: Vec<_> = scored
+            .iter()
+            .filter(|(q, _)| q == query)
+            .map(|(_, choice)| choice.clone())
+            .collect();
+        choices.sort();
+        choices
+    }
+}
+
+#[test]
+fn narrows_the_previous_results() {
+    let recording = Recording::default();
+    let picker = picker().matcher(recording.clone()).extended(false);
+    let events = ScriptedEvents::new()
+        .text("ea")
+        .text("r")
+        .key(KeyCode::Backspace)
+        .key(KeyCode::Esc);
+    let (_, drawn) = run(&picker, ANIMALS, events, false);
+    assert_eq!(drawn, screen(&["> ea", "1> bears"]));
+    // each query is only matched against the results of the one before, and going back to
+    // "ea" finds its results without matching again
+    assert_eq!(recording.choices("e").len(), ANIMALS.len());
+    assert_eq!(recording.choices("ea"), items(&["bears", "mice", "sheep"]));
+    assert_eq!(recording.choices("ear"), items(&["bears"]));
+}
+
+#[test]
+fn widens_on_alternatives() {
+    let events = ScriptedEvents::new()
+        .text("cats")
+        .text(" | dogs")
+        .key(KeyCode::Esc);
+    let (_, drawn) = run(&picker(), ANIMALS, events, false);
+    assert_eq!(drawn, screen(&["> cats | dogs", "1> dogs", "2: cats"]));
+}
+
+#[test]
+fn typing_an_anchor_finds_what_entering_it_does() {
+    let list = &["ab", "cab", "foobar", "bar"];
+    let prefix = || picker().algorithm(Algorithm::Prefix);
+    for (query, expected) in &[("ab$", &["ab", "cab"]), ("bar$", &["bar", "foobar"])] {
+        let entered = ScriptedEvents::new().key(KeyCode::Esc);
+        let (_, entered) = run(&prefix().query(*query), list, entered, false);
+        // one key at a time, so every char is searched for on its own
+        let typed = query
+            .chars()
+            .fold(ScriptedEvents::new(), |events, c| {
+                events.key(KeyCode::Char(c))
+            })
+            .key(KeyCode::Esc);
+        let (_, typed) = run(&prefix(), list, typed, false);
+        assert_eq!(typed, entered, "{}", query);
+        let mut shown = entered[1..=expected.len()]
+            .iter()
+            .map(|row| row[3..].trim_end().to_string())
+            .collect::<Vec<_>>();
+        shown.sort();
+        assert_eq!(shown, items(&expected[..]), "{}", query);
+    }
+}
+
+// Scripted events from a user who types the first `typed` of them, then waits until `ready`,
+// or a few seconds, before going on.
+struct Pausing<F> {
+    events: ScriptedEvents,
+    typed: usize,
+    ready: F,
+}
+
+impl<F> Events for Pausing<F>
+where
+    F: FnMut() -> bool + Send,
+{
+    fn poll(&mut self, timeout: Duration) -> crossterm::Result<bool> {
+        if self.typed == 0 {
+            let start = Instant::now();
+            while !(self.ready)() && start.elapsed() < Duration::from_secs(5) {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        self.events.poll(timeout)
+    }
+
+    fn read(&mut self) -> crossterm::Result<Event> {
+        self.typed = self.typed.saturating_sub(1);
+        self.events.read()
+    }
+}
+
+#[test]
+fn searches_likely_queries_ahead() {
+    let recording = Recording::default();
+    let picker = picker().matcher(recording.clone()).extended(false);
+    let events = Pausing {
+        events: ScriptedEvents::new().text("ea").key(KeyCode::Esc),
+        typed: 2,
+        ready: || !recording.choices("ear").is_empty(),
+    };
+    let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+    picker
+        .select_on(&mut terminal, items(ANIMALS), false)
+        .unwrap();
+    let frames = terminal.backend().frames();
+    assert_eq!(frames[frames.len() - 2], screen(&["> ea", "1> bears"]));
+    // "ear" was never typed
+    assert_eq!(recording.choices("ear"), items(&["bears"]));
+}
+
+#[test]
+fn accepts_from_cached_results() {
+    let events = ScriptedEvents::new()
+        .text("s")
+        .idle()
+        .text("h")
+        .key(KeyCode::Backspace)
+        .key(KeyCode::Down)
+        .key(KeyCode::Enter);
+    let (result, drawn) = run(&picker(), ANIMALS, events, false);
+    assert_eq!(result, Some(items(&["dogs"])));
+    assert_eq!(
+        drawn,
+        screen(&["> s", "1: sheep", "2> dogs", "3: cats", "4: bears", "5: goats"])
+    );
+}
+
+#[test]
+fn ranks_results_far_down_a_long_list() {
+    let picker = picker().matcher(Contains).extended(false);
+    let numbers: Vec<_> = (0..40_000).map(|i| i.to_string()).collect();
+    let pick = |events: ScriptedEvents| {
+        let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+        picker
+            .select_on(&mut terminal, numbers.clone(), false)
+            .unwrap()
+    };
+
+    let first = ScriptedEvents::new().text("99").key(KeyCode::Enter);
+    assert_eq!(pick(first), Some(items(&["99"])));
+    let last = ScriptedEvents::new()
+        .text("99")
+        .key_with(KeyCode::End, KeyModifiers::CONTROL)
+        .key(KeyCode::Up)
+        .key(KeyCode::Enter);
+    assert_eq!(pick(last), Some(items(&["39998"])));
+}
+
+#[test]
+fn skips_items_missing_chars_of_the_query() {
+    let recording = Recording {
+        prefilter: true,
+        ..Recording::default()
+    };
+    let picker = picker().matcher(recording.clone()).extended(false);
+    let events = ScriptedEvents::new().text("ar").key(KeyCode::Esc);
+    run(&picker, ANIMALS, events, false);
+    assert_eq!(recording.choices("a"), items(&["bears", "cats", "goats"]));
+    assert_eq!(recording.choices("ar"), items(&["bears"]));
+
+    // case is ignored, since matchers may ignore it
+    let events = ScriptedEvents::new().text("E").key(KeyCode::Esc);
+    run(&picker, ANIMALS, events, false);
+    assert_eq!(recording.choices("E"), items(&["bears", "mice", "sheep"]));
+}
+
+#[test]
+fn prefilter_keeps_every_match() {
+    let list = ["Éclair", "café", "CAFE", "crêpe", "tea"];
+    let filter = |query: &str| {
+        let events = ScriptedEvents::new().text(query).key(KeyCode::Esc);
+        let (_, drawn) = run(&picker(), &list, events, false);
+        let mut shown: Vec<_> = drawn[1..]
+            .iter()
+            .filter(|line| !line.is_empty())
+            .map(|line| line[3..].to_string())
+            .collect();
+        shown.sort();
+        shown
+    };
+    assert_eq!(filter("cafe"), items(&["CAFE"]));
+    assert_eq!(filter("'éc"), items(&["Éclair"]));
+    assert_eq!(filter("!caf"), items(&["crêpe", "tea", "Éclair"]));
+    assert_eq!(filter("tea | air"), items(&["tea", "Éclair"]));
+    assert_eq!(filter("^cr 'pe"), items(&["crêpe"]));
+}
+
+// Matches like `Contains` once the gate is open, and waits for it before then.
+#[derive(Clone, Default)]
+struct Gated(Arc<(Mutex<bool>, Condvar)>);
+
+impl Gated {
+    fn open(&self) {
+        let (open, opened) = &*self.0;
+        *open.lock().unwrap() = true;
+        opened.notify_all();
+    }
+
+    fn wait(&self) {
+        let (open, opened) = &*self.0;
+        let _open = opened.wait_while(open.lock().unwrap(), |open| !*open);
+    }
+}
+
+impl Matcher for Gated {
+    fn match_indices(&self, choice: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+        self.wait();
+        Contains.match_indices(choice, query)
+    }
+}
+
+// Scripted events that open a gate when the picker waits for keys while it searches, a few
+// frames after the first key was read.
+struct Opening {
+    events: ScriptedEvents,
+    gate: Gated,
+    read: Option<Instant>,
+}
+
+impl Events for Opening {
+    fn poll(&mut self, timeout: Duration) -> crossterm::Result<bool> {
+        self.events.poll(timeout)
+    }
+
+    fn poll_busy(&mut self, timeout: Duration) -> crossterm::Result<bool> {
+        let waited = self
+            .read
+            .is_some_and(|read| read.elapsed() > Duration::from_millis(100));
+        if waited {
+            self.gate.open();
+        }
+        self.events.poll_busy(timeout)
+    }
+
+    fn read(&mut self) -> crossterm::Result<Event> {
+        self.read.get_or_insert_with(Instant::now);
+        self.events.read()
+    }
+}
+
+#[test]
+fn shows_progress_until_the_search_is_done() {
+    let gated = Gated::default();
+    let picker = picker().matcher(gated.clone()).extended(false);
+    let events = ScriptedEvents::new().text("s").key(KeyCode::Enter);
+    let events = Opening {
+        events,
+        gate: gated,
+        read: None,
+    };
+    let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+    let result = picker.select_on(&mut terminal, items(ANIMALS), false);
+    assert_eq!(result.unwrap(), Some(items(&["dogs"])));
+
+    let frames = terminal.backend().frames();
+    // the items searched so far, out of all of them, while the matcher is held up
+    assert!(frames.iter().any(|frame| frame[0].ends_with(" 0/7")));
+    assert_eq!(
+        frames[frames.len() - 2],
+        screen(&["> s", "1> dogs", "2: cats", "3: bears", "4: sheep", "5: goats"])
+    );
+}
+
+#[test]
+fn shows_only_the_results_of_the_latest_query() {
+    let gated = Gated::default();
+    let picker = picker().matcher(gated.clone()).extended(false);
+    // "h" is typed while "s" is still being searched for
+    let events = ScriptedEvents::new()
+        .text("s")
+        .hurried(ScriptedEvents::new().text("h"))
+        .key(KeyCode::Enter);
+    let events = Opening {
+        events,
+        gate: gated,
+        read: None,
+    };
+    let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+    let result = picker.select_on(&mut terminal, items(ANIMALS), false);
+    assert_eq!(result.unwrap(), Some(items(&["sheep"])));
+
+    let frames = terminal.backend().frames();
+    // nothing found for "s" is ever shown
+    let searching = frames.iter().filter(|frame| frame[0].starts_with("> s"));
+    let mut rows = searching.flat_map(|frame| &frame[1..]);
+    assert!(rows.clone().any(|row| row == "1> sheep"));
+    assert!(rows.all(|row| row.is_empty() || row == "1> sheep"));
+    assert_eq!(frames[frames.len() - 2], screen(&["> sh", "1> sheep"]));
+}
+
+#[test]
+fn types_while_a_preview_is_made() {
+    let gated = Gated::default();
+    let gate = gated.clone();
+    let picker = picker()
+        .height(3)
+        .preview(move |item: &String| {
+            gate.wait();
+            format!("about {}", item)
+        })
+        .preview_window(PreviewPosition::Bottom, PreviewSize::Fixed(1));
+    let events = ScriptedEvents::new()
+        .hurried(ScriptedEvents::new().text("cat"))
+        .key(KeyCode::Esc);
+    let events = Opening {
+        events,
+        gate: gated,
+        read: None,
+    };
+    let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+    picker
+        .select_on(&mut terminal, items(ANIMALS), false)
+        .unwrap();
+
+    let frames = terminal.backend().frames();
+    // the results were drawn while the preview of "dogs" was held up, which is never shown
+    let separator = "─".repeat(30);
+    let waiting = screen(&["> cat", "1> cats", "", "", &separator]);
+    assert!(frames.contains(&waiting));
+    assert!(frames.iter().all(|frame| frame[5] != "about dogs"));
+    let shown = screen(&["> cat", "1> cats", "", "", &separator, "about cats"]);
+    assert_eq!(frames[frames.len() - 2], shown);
+}
+
+// Scripted events that send the rest of the items and close the source once the picker has
+// been waiting for keys while it loads for a few frames.
+struct Feeding {
+    events: ScriptedEvents,
+    sender: Option<Sender<String>>,
+    rest: Vec<String>,
+    loading: Option<Instant>,
+}
+
+impl Events for Feeding {
+    fn poll(&mut self, timeout: Duration) -> crossterm::Result<bool> {
+        self.events.poll(timeout)
+    }
+
+    fn poll_busy(&mut self, timeout: Duration) -> crossterm::Result<bool> {
+        let loading = self.loading.get_or_insert_with(Instant::now);
+        if loading.elapsed() > Duration::from_millis(100) {
+            if let Some(sender) = self.sender.take() {
+                for item in self.rest.drain(..) {
+                    sender.send(item).unwrap();
+                }
+            }
+        }
+        self.events.poll_busy(timeout)
+    }
+
+    fn read(&mut self) -> crossterm::Result<Event> {
+        self.events.read()
+    }
+}
+
+#[test]
+fn shows_items_loaded_so_far_until_the_source_ends() {
+    let (sender, source) = Source::channel();
+    for item in items(&["dogs", "cats"]) {
+        sender.send(item).unwrap();
+    }
+    let events = Feeding {
+        events: ScriptedEvents::new().text("m").key(KeyCode::Enter),
+        sender: Some(sender),
+        rest: items(&["mice"]),
+        loading: None,
+    };
+    let mut terminal = Terminal::new(TestBackend::new(30, 10), events);
+    let result = picker().select_on(&mut terminal, source, false);
+    assert_eq!(result.unwrap(), Some(items(&["mice"])));
+
+    let frames = terminal.backend().frames();
+    // a spinner and the number of items so far while loading, and keys wait until it's done
+    let loading: Vec<_> = frames
+        .iter()
+        .filter(|frame| frame[1..3] == ["1> dogs", "2: cats"] && frame[3].is_empty())
+        .map(|frame| frame[0].as_str())
+        .collect();
+    assert!(loading.contains(&">   - 2"));
+    assert!(loading.contains(&">   \\ 2"));
+    assert_eq!(
+        frames[frames.len() - 3],
+        screen(&[">", "1> dogs", "2: cats", "3: mice"])
+    );
+    assert_eq!(frames[frames.len() - 2], screen(&["> m", "1> mice"]));
+}